@@ -0,0 +1,30 @@
+//! Feeds arbitrary bytes as CSV input through the parser into an order book, with and without
+//! trading. Malformed records are skipped, but neither the parser nor the order book may panic.
+//! The order book checks its invariants after every message.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use orderbook::order_book::order::{self, Order};
+use orderbook::order_book::OrderBook;
+use std::sync::mpsc::channel;
+
+fuzz_target!(|data: &[u8]| {
+    for trading in [false, true] {
+        let (output_sender, output_receiver) = channel();
+        let mut order_book = OrderBook::new(output_sender, trading).with_invariant_checks();
+        let mut reader = order::csv_reader().from_reader(data);
+        for result in reader.records() {
+            // Input that is no valid CSV ends the input like in `process_input_orders`
+            let record = match result {
+                Ok(record) => record,
+                Err(_) => break,
+            };
+            if let Some(order) = Order::parse(&record) {
+                order_book.add_order(order);
+            }
+        }
+        std::mem::drop(order_book);
+        output_receiver.iter().for_each(drop);
+    }
+});
@@ -0,0 +1,99 @@
+//! Defines a benchmark that measures the throughput and latency of an order book
+
+use crate::order_book::order::Order;
+use crate::order_book::OrderBook;
+use std::time::{Duration, Instant};
+
+/// Percentiles of the latency that are reported
+const PERCENTILES: [(&str, f64); 3] = [("p50", 0.5), ("p99", 0.99), ("p99.9", 0.999)];
+
+/// Measurements of one benchmark run
+pub struct Report {
+    /// Total time spent in `OrderBook::add_order`
+    elapsed: Duration,
+    /// Latency of every call of `OrderBook::add_order` in nanoseconds, sorted ascending
+    latencies: Vec<u64>,
+}
+
+impl Report {
+    /// Get the number of processed orders per second
+    pub fn orders_per_second(&self) -> f64 {
+        match self.elapsed.as_secs_f64() {
+            seconds if seconds > 0.0 => self.latencies.len() as f64 / seconds,
+            _ => 0.0,
+        }
+    }
+
+    /// Get a percentile of the latency
+    ///
+    /// # Args
+    /// - `percentile`: Share of the messages that were processed at least as fast, e.g. `0.99`
+    ///
+    /// # Return
+    /// - The latency in nanoseconds, `None` if no orders were processed
+    pub fn latency(&self, percentile: f64) -> Option<u64> {
+        let last = self.latencies.len().checked_sub(1)?;
+        let index = (last as f64 * percentile).round() as usize;
+        Some(self.latencies[index.min(last)])
+    }
+
+    /// Write the report as CSV lines in the formats
+    /// `BO, orders, elapsed_ns, orders_per_second`,
+    /// `BL, percentile, latency_ns` for the p50, p99, p99.9 and maximum latency and
+    /// `BH, upper_bound_ns, count` for a histogram of the latency with buckets of powers of two.
+    ///
+    /// # Return
+    /// - The lines of the report
+    pub fn lines(&self) -> Vec<String> {
+        let mut lines = vec![format!(
+            "BO, {}, {}, {:.0}",
+            self.latencies.len(),
+            self.elapsed.as_nanos(),
+            self.orders_per_second()
+        )];
+        let percentiles = PERCENTILES.iter().chain(&[("max", 1.0)]);
+        for (name, percentile) in percentiles {
+            if let Some(latency) = self.latency(*percentile) {
+                lines.push(format!("BL, {}, {}", name, latency));
+            }
+        }
+        let mut upper_bound = 1;
+        let mut latencies = self.latencies.iter().peekable();
+        while latencies.peek().is_some() {
+            let mut count = 0;
+            while latencies
+                .next_if(|latency| **latency <= upper_bound)
+                .is_some()
+            {
+                count += 1;
+            }
+            if count > 0 {
+                lines.push(format!("BH, {}, {}", upper_bound, count));
+            }
+            upper_bound = upper_bound.saturating_mul(2);
+        }
+        lines
+    }
+}
+
+/// Process orders with an order book and measure the latency of every message
+///
+/// # Args
+/// - `order_book`: Order book to process the orders with
+/// - `orders`: Orders to be processed, generated upfront so generating them is not measured
+///
+/// # Return
+/// - A `Report` with the measurements
+pub fn run(order_book: &mut OrderBook, orders: Vec<Order>) -> Report {
+    let mut latencies = Vec::with_capacity(orders.len());
+    let mut elapsed = Duration::ZERO;
+    for order in orders {
+        let start = Instant::now();
+        order_book.add_order(order);
+        let latency = start.elapsed();
+        elapsed += latency;
+        latencies.push(latency.as_nanos() as u64);
+    }
+    latencies.sort_unstable();
+    Report { elapsed, latencies }
+}
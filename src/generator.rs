@@ -0,0 +1,292 @@
+//! Defines a seeded generator of synthetic order flow for benchmarks and tests
+
+use crate::order_book::order::{Kind, Order, Side, TimeInForce};
+use crate::order_book::price::Price;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::str::FromStr;
+
+/// Symbol of all generated orders
+const SYMBOL: &str = "SYN";
+/// Price the generated orders are placed around, in units of the tick size
+const MID_TICKS: i64 = 10_000;
+/// Tick size of the generated prices in price units, i.e. 0.01
+const TICK_UNITS: i64 = 1_000_000;
+/// Number of users the generated orders are spread over
+const USERS: u64 = 10;
+
+/// Enumeration to specify the distribution of a generated positive integer
+#[derive(Clone, Copy, PartialEq)]
+pub enum Distribution {
+    /// Always the given value
+    Constant(u64),
+    /// Uniformly distributed between the given minimum and maximum, both inclusive
+    Uniform(u64, u64),
+    /// Exponentially distributed with the given mean, rounded to the nearest integer of at least 1
+    Exponential(u64),
+}
+
+impl Distribution {
+    /// Draw a value from the distribution
+    fn sample(&self, rng: &mut StdRng) -> u64 {
+        match *self {
+            Distribution::Constant(value) => value,
+            Distribution::Uniform(min, max) => rng.gen_range(min..=max),
+            Distribution::Exponential(mean) => {
+                let uniform: f64 = rng.gen();
+                let value = -(mean as f64) * (1.0 - uniform).ln();
+                (value.round() as u64).max(1)
+            }
+        }
+    }
+}
+
+impl FromStr for Distribution {
+    type Err = String;
+
+    /// Interpret a string in the format `CONSTANT:value`, `UNIFORM:min-max` or
+    /// `EXPONENTIAL:mean`, where all values are positive integers
+    fn from_str(distribution: &str) -> Result<Distribution, String> {
+        let error = || format!("Invalid distribution {}", distribution);
+        let (kind, values) = distribution.split_once(':').ok_or_else(error)?;
+        let parse = |value: &str| {
+            value
+                .parse::<u64>()
+                .ok()
+                .filter(|value| *value > 0)
+                .ok_or_else(error)
+        };
+        match kind {
+            "CONSTANT" => Ok(Distribution::Constant(parse(values)?)),
+            "UNIFORM" => {
+                let (min, max) = values.split_once('-').ok_or_else(error)?;
+                let (min, max) = (parse(min)?, parse(max)?);
+                if min > max {
+                    return Err(error());
+                }
+                Ok(Distribution::Uniform(min, max))
+            }
+            "EXPONENTIAL" => Ok(Distribution::Exponential(parse(values)?)),
+            _ => Err(error()),
+        }
+    }
+}
+
+impl fmt::Display for Distribution {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Distribution::Constant(value) => write!(f, "CONSTANT:{}", value),
+            Distribution::Uniform(min, max) => write!(f, "UNIFORM:{}-{}", min, max),
+            Distribution::Exponential(mean) => write!(f, "EXPONENTIAL:{}", mean),
+        }
+    }
+}
+
+/// Parameters of the generated order flow
+#[derive(Clone)]
+pub struct Config {
+    /// Seed of the random number generator, the same seed always generates the same orders
+    pub seed: u64,
+    /// Distance of passive orders from the mid price in ticks
+    pub price: Distribution,
+    /// Quantity of new orders
+    pub size: Distribution,
+    /// Share of cancellations of resting orders among all generated orders
+    pub cancel_ratio: f64,
+    /// Share of new orders that trade against the best resting order of the opposite side
+    pub aggressive_ratio: f64,
+}
+
+/// Resting order as tracked by the generator
+struct Resting {
+    user: u64,
+    id: u64,
+    qty: u64,
+}
+
+/// Generates an endless stream of new orders and cancellations for one symbol.
+///
+/// The generator mirrors the book its orders build up when they are processed in trade mode,
+/// so cancellations always address resting orders and aggressive orders always find a match.
+/// Passive orders never cross the mid price.
+pub struct Generator {
+    config: Config,
+    rng: StdRng,
+    next_id: u64,
+    bids: BTreeMap<Price, Vec<Resting>>,
+    asks: BTreeMap<Price, Vec<Resting>>,
+    /// Side and price of all resting orders, to choose an order to cancel
+    resting: Vec<(Side, Price, u64)>,
+    /// Position of every resting order in `resting` by order id
+    positions: HashMap<u64, usize>,
+}
+
+impl Generator {
+    /// Factory function for constructing a new Generator
+    ///
+    /// # Args
+    /// - `config`: Parameters of the generated order flow
+    ///
+    /// # Return
+    /// A new `Generator` instance with an empty book
+    pub fn new(config: Config) -> Generator {
+        Generator {
+            rng: StdRng::seed_from_u64(config.seed),
+            config,
+            next_id: 1,
+            bids: BTreeMap::new(),
+            asks: BTreeMap::new(),
+            resting: Vec::new(),
+            positions: HashMap::new(),
+        }
+    }
+
+    /// Generate a new order on a random side, either passive or aggressive
+    fn new_order(&mut self) -> Order {
+        let side = if self.rng.gen_bool(0.5) {
+            Side::Buy
+        } else {
+            Side::Sell
+        };
+        let id = self.next_id;
+        self.next_id += 1;
+        let user = self.rng.gen_range(1..=USERS);
+        if self.rng.gen_bool(self.config.aggressive_ratio) {
+            if let Some((price, qty)) = self.take_best(&side) {
+                return new_order(user, id, side, price, qty);
+            }
+        }
+        let distance = self.config.price.sample(&mut self.rng) as i64;
+        let ticks = match side {
+            Side::Buy => MID_TICKS - distance.min(MID_TICKS - 1),
+            Side::Sell => MID_TICKS + distance,
+        };
+        let price = Price::from_units(ticks * TICK_UNITS);
+        let qty = self.config.size.sample(&mut self.rng);
+        self.book(&side)
+            .entry(price)
+            .or_default()
+            .push(Resting { user, id, qty });
+        self.positions.insert(id, self.resting.len());
+        self.resting.push((side.clone(), price, id));
+        new_order(user, id, side, price, qty)
+    }
+
+    /// Remove the resting order with the highest priority on the side opposite to an order
+    ///
+    /// # Args
+    /// - `side`: Side of the aggressive order
+    ///
+    /// # Return
+    /// - Price and quantity of the removed order, `None` if the opposite side is empty
+    fn take_best(&mut self, side: &Side) -> Option<(Price, u64)> {
+        let opposite = side.opposite();
+        let book = self.book(&opposite);
+        let mut entry = match opposite {
+            Side::Buy => book.last_entry()?,
+            Side::Sell => book.first_entry()?,
+        };
+        let (price, resting) = (*entry.key(), entry.get_mut().remove(0));
+        self.remove_empty_level(&opposite, price);
+        self.forget(resting.id);
+        Some((price, resting.qty))
+    }
+
+    /// Generate the cancellation of a random resting order
+    ///
+    /// # Return
+    /// - The cancellation, `None` if there are no resting orders
+    fn cancellation(&mut self) -> Option<Order> {
+        if self.resting.is_empty() {
+            return None;
+        }
+        let (side, price, id) = self.resting[self.rng.gen_range(0..self.resting.len())].clone();
+        let level = self.book(&side).get_mut(&price).unwrap();
+        let resting = level.remove(level.iter().position(|r| r.id == id).unwrap());
+        self.remove_empty_level(&side, price);
+        self.forget(id);
+        let mut order = Order::new_command(Kind::Cancel);
+        order.user = resting.user;
+        order.user_order_id = id;
+        Some(order)
+    }
+
+    /// Get the resting orders of one side by price
+    fn book(&mut self, side: &Side) -> &mut BTreeMap<Price, Vec<Resting>> {
+        match side {
+            Side::Buy => &mut self.bids,
+            Side::Sell => &mut self.asks,
+        }
+    }
+
+    /// Remove a price level of one side if it has no orders left
+    fn remove_empty_level(&mut self, side: &Side, price: Price) {
+        let book = self.book(side);
+        if book.get(&price).is_some_and(Vec::is_empty) {
+            book.remove(&price);
+        }
+    }
+
+    /// Stop tracking a resting order that traded or was cancelled
+    fn forget(&mut self, id: u64) {
+        let position = self.positions.remove(&id).unwrap();
+        self.resting.swap_remove(position);
+        if let Some((_, _, moved)) = self.resting.get(position) {
+            self.positions.insert(*moved, position);
+        }
+    }
+}
+
+impl Iterator for Generator {
+    type Item = Order;
+
+    fn next(&mut self) -> Option<Order> {
+        if self.rng.gen_bool(self.config.cancel_ratio) {
+            if let Some(order) = self.cancellation() {
+                return Some(order);
+            }
+        }
+        Some(self.new_order())
+    }
+}
+
+/// Create a new day order of the generated symbol
+fn new_order(user: u64, id: u64, side: Side, price: Price, qty: u64) -> Order {
+    Order {
+        kind: Kind::New,
+        user,
+        symbol: SYMBOL.to_string(),
+        price,
+        qty,
+        side,
+        user_order_id: id,
+        time_in_force: TimeInForce::Day,
+        session: None,
+        timestamp: None,
+    }
+}
+
+/// Format a generated order as record of the CSV input
+///
+/// # Args
+/// - `order`: New order or cancellation
+///
+/// # Return
+/// - The CSV line
+pub fn to_csv(order: &Order) -> String {
+    match order.kind {
+        Kind::Cancel => format!("C, {}, {}", order.user, order.user_order_id),
+        _ => {
+            let side = match order.side {
+                Side::Buy => "B",
+                Side::Sell => "S",
+            };
+            format!(
+                "N, {}, {}, {}, {}, {}, {}",
+                order.user, order.symbol, order.price, order.qty, side, order.user_order_id
+            )
+        }
+    }
+}
@@ -0,0 +1,236 @@
+//! Defines an HTTP server for order entry and book queries, which exchanges JSON bodies in the
+//! format of the JSON Lines input and output
+
+use crate::order_book::event::{Event, Message};
+use crate::order_book::order::Side;
+use crate::order_book::price::Price;
+use crate::order_book::status::Status;
+use crate::order_book::{jsonl, OrderBook};
+use serde_json::{json, Map, Value};
+use std::sync::mpsc::Receiver;
+use tiny_http::{Header, Method, Response, Server};
+
+/// Number of price levels per side returned by a book query without `depth` parameter
+const DEFAULT_DEPTH: usize = 10;
+
+/// Order book served over HTTP. Every request is processed completely before the next one, so the
+/// output of a message is known when its response is sent.
+pub struct Api<F> {
+    order_book: OrderBook,
+    output_receiver: Receiver<Event>,
+    /// Receives every output event after it was added to a response
+    output: F,
+    /// All trades of the order book as JSON objects
+    trades: Vec<Value>,
+}
+
+impl<F: FnMut(Event)> Api<F> {
+    /// Factory function for constructing a new Api
+    ///
+    /// # Args
+    /// - `order_book`: Order book to be served
+    /// - `output_receiver`: Receiver of the output events of the order book
+    /// - `output`: Receives every output event, e.g. to print it like the CLI
+    ///
+    /// # Return
+    /// A new `Api` instance
+    pub fn new(order_book: OrderBook, output_receiver: Receiver<Event>, output: F) -> Api<F> {
+        Api {
+            order_book,
+            output_receiver,
+            output,
+            trades: Vec::new(),
+        }
+    }
+
+    /// Answer all requests of a server until it is unblocked
+    ///
+    /// # Args
+    /// - `server`: Server to receive the requests from
+    pub fn serve(&mut self, server: &Server) {
+        for mut request in server.incoming_requests() {
+            let mut body = String::new();
+            let (status, value) = match request.as_reader().read_to_string(&mut body) {
+                Ok(_) => self.handle(request.method(), request.url(), &body),
+                Err(_) => error(400, "MALFORMED_REQUEST"),
+            };
+            let header = Header::from_bytes("Content-Type", "application/json").unwrap();
+            let response = Response::from_string(value.to_string())
+                .with_status_code(status)
+                .with_header(header);
+            // A client that disconnected early does not affect the order book
+            let _ = request.respond(response);
+        }
+    }
+
+    /// Handle one request
+    ///
+    /// # Args
+    /// - `method`: HTTP method of the request
+    /// - `url`: Path and query of the request
+    /// - `body`: Body of the request, empty if there is none
+    ///
+    /// # Return
+    /// - HTTP status code and JSON body of the response
+    pub fn handle(&mut self, method: &Method, url: &str, body: &str) -> (u16, Value) {
+        let (path, query) = url.split_once('?').unwrap_or((url, ""));
+        let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+        match (method, segments.as_slice()) {
+            (Method::Post, ["orders"]) => match parse_body(body) {
+                Some(object) => self.submit("new", object, 201),
+                None => error(400, "MALFORMED_REQUEST"),
+            },
+            (Method::Delete, ["orders", user, order_id]) => {
+                // The book acknowledges cancellations of orders it does not hold
+                if let (Ok(user), Ok(order_id)) = (user.parse(), order_id.parse()) {
+                    match self.order_book.order_status(user, order_id) {
+                        Some(Status::Resting { .. }) => (),
+                        _ => return error(404, "UNKNOWN_ORDER"),
+                    }
+                }
+                let object = order_key(Map::new(), user, order_id);
+                self.submit("cancel", object, 200)
+            }
+            (Method::Patch, ["orders", user, order_id]) => match parse_body(body) {
+                Some(object) => self.submit("amend", order_key(object, user, order_id), 200),
+                None => error(400, "MALFORMED_REQUEST"),
+            },
+            (Method::Get, ["book", symbol]) => {
+                let depth = query
+                    .split('&')
+                    .find_map(|parameter| parameter.strip_prefix("depth="))
+                    .map_or(Some(DEFAULT_DEPTH), |depth| depth.parse().ok());
+                match depth {
+                    Some(depth) => (200, self.book(symbol, depth)),
+                    None => error(400, "MALFORMED_REQUEST"),
+                }
+            }
+            (Method::Get, ["trades"]) => (200, json!({ "trades": self.trades })),
+            _ => error(404, "NOT_FOUND"),
+        }
+    }
+
+    /// Process a message and report its output
+    ///
+    /// # Args
+    /// - `kind`: Type of the message as in the JSON Lines input
+    /// - `object`: Fields of the message
+    /// - `status`: HTTP status code if the message is accepted
+    ///
+    /// # Return
+    /// - HTTP status code and JSON body of the response, which lists the output of the message.
+    ///   Rejected messages respond with an error status depending on the reason.
+    fn submit(&mut self, kind: &str, mut object: Map<String, Value>, status: u16) -> (u16, Value) {
+        object.insert("type".to_string(), Value::from(kind));
+        let order = match jsonl::parse_object(&object) {
+            Some(order) => order,
+            None => return error(400, "MALFORMED_REQUEST"),
+        };
+        self.order_book.add_order(order);
+        let mut events = Vec::new();
+        let mut reject = None;
+        while let Ok(event) = self.output_receiver.try_recv() {
+            let value = jsonl::event_object(&event);
+            match &event.message {
+                Message::Trade { .. } => self.trades.push(value.clone()),
+                // Orders that cross the book are rejected without reason
+                Message::Reject { reason, .. } if reject.is_none() => {
+                    reject = Some(reason.clone().unwrap_or_else(|| "REJECTED".to_string()))
+                }
+                _ => (),
+            }
+            events.push(value);
+            (self.output)(event);
+        }
+        match reject {
+            Some(reason) => (
+                reject_status(&reason),
+                json!({ "error": reason, "events": events }),
+            ),
+            None => (status, json!({ "events": events })),
+        }
+    }
+
+    /// Describe the price levels of the orders of one symbol
+    ///
+    /// # Args
+    /// - `symbol`: Symbol of the orders
+    /// - `depth`: Maximum number of levels per side
+    ///
+    /// # Return
+    /// - JSON object with the levels of both sides, starting at the best price
+    fn book(&self, symbol: &str, depth: usize) -> Value {
+        let levels = |side: &Side| {
+            let mut levels: Vec<(Price, u64, u64)> = Vec::new();
+            for order in self.order_book.orders(side).filter(|o| o.symbol == symbol) {
+                if let Some((price, qty, orders)) = levels.last_mut() {
+                    if *price == order.price {
+                        *qty = qty.saturating_add(order.qty);
+                        *orders += 1;
+                        continue;
+                    }
+                }
+                if levels.len() == depth {
+                    break;
+                }
+                levels.push((order.price, order.qty, 1));
+            }
+            levels
+                .into_iter()
+                .map(|(price, qty, orders)| {
+                    json!({ "price": price.to_string(), "qty": qty, "orders": orders })
+                })
+                .collect::<Vec<_>>()
+        };
+        json!({
+            "symbol": symbol,
+            "bids": levels(&Side::Buy),
+            "asks": levels(&Side::Sell),
+        })
+    }
+}
+
+/// Interpret the body of a request as JSON object
+fn parse_body(body: &str) -> Option<Map<String, Value>> {
+    match serde_json::from_str(body) {
+        Ok(Value::Object(object)) => Some(object),
+        _ => None,
+    }
+}
+
+/// Add the user and order id from the path of a request to the fields of a message
+fn order_key(mut object: Map<String, Value>, user: &str, order_id: &str) -> Map<String, Value> {
+    object.insert("user".to_string(), Value::from(user));
+    object.insert("order_id".to_string(), Value::from(order_id));
+    object
+}
+
+/// Create an error response
+///
+/// # Args
+/// - `status`: HTTP status code
+/// - `reason`: Reason code of the error
+///
+/// # Return
+/// - HTTP status code and JSON body of the response
+fn error(status: u16, reason: &str) -> (u16, Value) {
+    (status, json!({ "error": reason }))
+}
+
+/// Map the reason code of a reject to an HTTP status code
+///
+/// # Args
+/// - `reason`: Reason code of the reject
+///
+/// # Return
+/// - 404 for unknown orders, 409 for messages that conflict with the state of the book, 403 for
+///   breached risk limits and 422 for orders that violate the rules of the instrument or the price
+///   bands
+fn reject_status(reason: &str) -> u16 {
+    match reason {
+        "UNKNOWN_ORDER" => 404,
+        "CROSSING" | "DUPLICATE_ORDER_ID" | "HALTED" | "CLOSED" => 409,
+        reason if reason.starts_with("RISK_") => 403,
+        _ => 422,
+    }
+}
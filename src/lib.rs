@@ -0,0 +1,3 @@
+//! Order book library, which the application and the fuzz targets are built on
+
+pub mod order_book;
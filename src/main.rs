@@ -1,47 +1,396 @@
-use clap::Parser;
+use clap::{Parser, Subcommand};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::net::TcpListener;
 use std::path::PathBuf;
 use std::sync::mpsc::{channel, Sender};
 use std::thread;
+use std::time::Duration;
 
-mod order_book;
+mod bench;
+mod generator;
+#[cfg(feature = "http")]
+mod http;
+mod network;
+mod tui;
+#[cfg(feature = "websocket")]
+mod websocket;
+
+use orderbook::order_book;
 
 /// CLI tool that implements an order book for a given input file
 #[derive(Parser)]
-#[clap(author, version, about, long_about = None)]
+#[clap(author, version, about, long_about = None, subcommand_negates_reqs = true)]
 struct Args {
+    #[clap(subcommand)]
+    command: Option<Command>,
     /// Trade mode, provided then orders that cross the book will be traded instead of rejected
     #[clap(short, long, value_parser, default_value_t = false)]
     trade: bool,
+    /// Reference price the static price band is centered around
+    #[clap(long, value_parser)]
+    reference_price: Option<order_book::price::Price>,
+    /// Width of the static price band around the reference price in basis points
+    #[clap(long, value_parser)]
+    static_band: Option<u32>,
+    /// Width of the dynamic price band around the last trade price in basis points
+    #[clap(long, value_parser)]
+    dynamic_band: Option<u32>,
+    /// Session state to move into when a trade would print outside the dynamic price band, either
+    /// PREOPEN or HALTED
+    #[clap(
+        long,
+        value_parser = order_book::price_band::parse_volatility_state,
+        default_value = "PREOPEN"
+    )]
+    volatility_state: order_book::session::State,
+    /// Path to a CSV file with instrument reference data new orders are validated against
+    #[clap(long, parse(from_os_str))]
+    instruments: Option<PathBuf>,
+    /// Path to a CSV file with risk limits per user new orders are checked against
+    #[clap(long, parse(from_os_str))]
+    risk_limits: Option<PathBuf>,
+    /// Path to a CSV file the positions and profit and loss of all users are written to at the end
+    #[clap(long, parse(from_os_str))]
+    positions: Option<PathBuf>,
+    /// Address to accept network sessions on instead of reading an input file, e.g. 127.0.0.1:7000
+    #[clap(long, value_parser)]
+    listen: Option<String>,
+    /// Address to serve the HTTP API on instead of reading an input file, e.g. 127.0.0.1:8080
+    #[cfg(feature = "http")]
+    #[clap(long, value_parser)]
+    http: Option<String>,
+    /// Address to stream market data over WebSocket on, e.g. 127.0.0.1:9000
+    #[cfg(feature = "websocket")]
+    #[clap(long, value_parser)]
+    market_data: Option<String>,
+    /// Cancel all resting orders of a network session when it disconnects or misses heartbeats
+    #[clap(long, value_parser, default_value_t = false)]
+    cancel_on_disconnect: bool,
+    /// Time in milliseconds a network session may stay silent before it is disconnected, must be
+    /// positive
+    #[clap(long, value_parser = network::parse_heartbeat_timeout)]
+    heartbeat_timeout: Option<Duration>,
+    /// Path to a CSV file every trade is written to, separate from the other output
+    #[clap(long, parse(from_os_str))]
+    trade_tape: Option<PathBuf>,
+    /// Path to a CSV file an audit report with the lifecycle of every order is written to at the end
+    #[clap(long, parse(from_os_str))]
+    audit: Option<PathBuf>,
+    /// Path to a CSV file a report of order book statistics is written to at the end
+    #[clap(long, parse(from_os_str))]
+    statistics: Option<PathBuf>,
+    /// Path to a file the bars aggregated from all trades are written to at the end
+    #[clap(long, parse(from_os_str))]
+    bars: Option<PathBuf>,
+    /// Interval of the bars, either TIME:length in units of the event timestamps, VOLUME:quantity
+    /// or TRADES:count
+    #[clap(long, value_parser, default_value = "TIME:60")]
+    bar_interval: order_book::bar::Interval,
+    /// Format of the bars file, either CSV or JSON
+    #[clap(long, value_parser, default_value = "CSV")]
+    bar_format: order_book::bar::Format,
+    /// Clock the output events are timestamped with, either LOGICAL for the event timestamps of the
+    /// input records or WALL for milliseconds since the Unix epoch
+    #[clap(long, value_parser, default_value = "LOGICAL")]
+    clock: order_book::event::Clock,
+    /// Prefix every output line with the sequence number and timestamp of its event
+    #[clap(long, value_parser, default_value_t = false)]
+    event_header: bool,
+    /// Format of the input file or network sessions, either CSV, JSONL or BINARY, where network
+    /// sessions only support CSV and BINARY
+    #[clap(long, value_parser, default_value = "CSV")]
+    input_format: order_book::format::Format,
+    /// Format of the output, either CSV, JSONL or BINARY
+    #[clap(long, value_parser, default_value = "CSV")]
+    output_format: order_book::format::Format,
+    /// Path to a file the input orders are converted to in the binary format instead of being
+    /// processed
+    #[clap(long, parse(from_os_str))]
+    convert: Option<PathBuf>,
+    /// Path to a file with output events in the binary format that is printed in the CSV output
+    /// format instead of processing orders
+    #[clap(long, parse(from_os_str))]
+    decode: Option<PathBuf>,
+    /// Number of synthetic orders to process as benchmark instead of reading an input file, which
+    /// reports the throughput and latency. Orders are always traded in a benchmark.
+    #[clap(long, value_parser)]
+    bench: Option<usize>,
+    /// Number of synthetic orders to print as CSV input instead of processing orders
+    #[clap(long, value_parser)]
+    generate: Option<usize>,
+    /// Seed of the synthetic orders, the same seed always generates the same orders
+    #[clap(long, value_parser, default_value_t = 1)]
+    seed: u64,
+    /// Distance of synthetic passive orders from the mid price in ticks, either CONSTANT:value,
+    /// UNIFORM:min-max or EXPONENTIAL:mean
+    #[clap(long, value_parser, default_value = "EXPONENTIAL:5")]
+    price_distribution: generator::Distribution,
+    /// Quantity of synthetic orders, either CONSTANT:value, UNIFORM:min-max or EXPONENTIAL:mean
+    #[clap(long, value_parser, default_value = "UNIFORM:1-100")]
+    size_distribution: generator::Distribution,
+    /// Share of cancellations among the synthetic orders, between 0 and 1
+    #[clap(long, value_parser, default_value_t = 0.45)]
+    cancel_ratio: f64,
+    /// Share of synthetic new orders that trade against the opposite side, between 0 and 1
+    #[clap(long, value_parser, default_value_t = 0.1)]
+    aggressive_ratio: f64,
     /// Path to a input CSV file
     #[clap(parse(from_os_str))]
-    file: PathBuf,
+    #[cfg_attr(
+        feature = "http",
+        clap(required_unless_present_any = &["listen", "http", "decode", "bench", "generate"])
+    )]
+    #[cfg_attr(
+        not(feature = "http"),
+        clap(required_unless_present_any = &["listen", "decode", "bench", "generate"])
+    )]
+    file: Option<PathBuf>,
+}
+
+/// Modes that replace processing the input file
+#[derive(Subcommand)]
+enum Command {
+    /// Step through an input file in an interactive terminal UI instead of printing the output
+    Tui {
+        /// Path to a input CSV or JSON Lines file
+        #[clap(parse(from_os_str))]
+        file: PathBuf,
+    },
 }
 
 fn main() {
     let args = Args::parse();
+    if let Some(path) = &args.decode {
+        decode_binary_events(path, args.event_header)
+            .unwrap_or_else(|_| panic!("Could not read file {}", path.display()));
+        return;
+    }
+    if let Some(path) = &args.convert {
+        let file = args.file.as_ref().unwrap();
+        convert_input_orders(file, path)
+            .unwrap_or_else(|error| panic!("Could not convert file {}: {}", file.display(), error));
+        return;
+    }
+    let ratios = [args.cancel_ratio, args.aggressive_ratio];
+    if ratios.iter().any(|ratio| !(0.0..=1.0).contains(ratio)) {
+        panic!("Ratios of synthetic orders must be between 0 and 1");
+    }
+    let generator = generator::Generator::new(generator::Config {
+        seed: args.seed,
+        price: args.price_distribution,
+        size: args.size_distribution,
+        cancel_ratio: args.cancel_ratio,
+        aggressive_ratio: args.aggressive_ratio,
+    });
+    if let Some(count) = args.generate {
+        let stdout = std::io::stdout();
+        let mut stdout = stdout.lock();
+        for order in generator.take(count) {
+            writeln!(stdout, "{}", generator::to_csv(&order)).unwrap();
+        }
+        return;
+    }
+    let price_bands = order_book::price_band::PriceBands {
+        reference_price: args.reference_price,
+        static_band: args.static_band,
+        dynamic_band: args.dynamic_band,
+        volatility_state: args.volatility_state,
+    };
+    let instruments = match &args.instruments {
+        Some(path) => order_book::instrument::load(path)
+            .unwrap_or_else(|error| panic!("Could not load file {}: {}", path.display(), error)),
+        None => HashMap::new(),
+    };
+    let risk_manager = match &args.risk_limits {
+        Some(path) => order_book::risk::load(path)
+            .unwrap_or_else(|error| panic!("Could not load file {}: {}", path.display(), error)),
+        None => order_book::risk::RiskManager::default(),
+    };
+    #[cfg(feature = "http")]
+    let serves_http = args.http.is_some();
+    #[cfg(not(feature = "http"))]
+    let serves_http = false;
+    let reports = [&args.positions, &args.statistics, &args.bars, &args.audit];
+    if (args.command.is_some() || serves_http) && reports.iter().any(|path| path.is_some()) {
+        panic!("The terminal UI and the HTTP API do not support reports written at the end");
+    }
+    #[cfg(feature = "websocket")]
+    let market_data = args.market_data.as_ref().map(|address| {
+        let listener = TcpListener::bind(address)
+            .unwrap_or_else(|_| panic!("Could not listen on {}", address));
+        let (update_sender, update_receiver) = channel();
+        thread::spawn(move || websocket::serve(listener, update_receiver));
+        update_sender
+    });
+    // Every mode builds its order books the same way, the terminal UI once per replay. The
+    // synthetic aggressive orders of a benchmark would only be rejected without trading.
+    let match_orders = args.trade || args.bench.is_some();
+    let new_order_book = |output_sender| {
+        let mut order_book = order_book::OrderBook::new(output_sender, match_orders)
+            .with_price_bands(price_bands.clone())
+            .with_instruments(instruments.clone())
+            .with_risk_manager(risk_manager.clone())
+            .with_clock(args.clock);
+        if args.bars.is_some() {
+            order_book = order_book.with_bars(args.bar_interval);
+        }
+        if args.audit.is_some() {
+            order_book = order_book.with_audit_trail();
+        }
+        if let Some(path) = &args.trade_tape {
+            let file = File::create(path)
+                .unwrap_or_else(|_| panic!("Could not write file {}", path.display()));
+            order_book = order_book.with_trade_tape(BufWriter::new(file));
+        }
+        #[cfg(feature = "websocket")]
+        if let Some(update_sender) = &market_data {
+            order_book = order_book.with_market_data(update_sender.clone());
+        }
+        order_book
+    };
+    if let Some(Command::Tui { file: path }) = &args.command {
+        let records = tui::load_records(path, args.input_format)
+            .unwrap_or_else(|error| panic!("Could not read file {}: {}", path.display(), error));
+        tui::run(&path.display().to_string(), records, new_order_book)
+            .expect("Could not run the terminal UI");
+        return;
+    }
+    #[cfg(feature = "http")]
+    if let Some(address) = &args.http {
+        let server = tiny_http::Server::http(address)
+            .unwrap_or_else(|_| panic!("Could not listen on {}", address));
+        let (output_sender, output_receiver) = channel();
+        let stdout = std::io::stdout();
+        let mut stdout = stdout.lock();
+        let output =
+            |event| write_event(&mut stdout, &event, args.output_format, args.event_header);
+        http::Api::new(new_order_book(output_sender), output_receiver, output).serve(&server);
+        return;
+    }
     let (order_sender, order_receiver) = channel();
     let (output_sender, output_receiver) = channel();
-    let mut order_book = order_book::OrderBook::new(output_sender, args.trade);
-    let read_handle = thread::spawn(move || {
-        process_input_orders(&args.file, order_sender)
-            .unwrap_or_else(|_| panic!("Could not open file {}", &args.file.display()))
-    });
-    while let Ok(data) = order_receiver.recv() {
-        order_book.add_order(data);
+    let mut order_book = new_order_book(output_sender);
+    if let Some(count) = args.bench {
+        let orders = generator.take(count).collect();
+        // Discard the output, so only the order book is measured
+        let output_handle = thread::spawn(move || output_receiver.iter().count());
+        let report = bench::run(&mut order_book, orders);
+        std::mem::drop(order_book);
+        output_handle.join().unwrap();
+        for line in report.lines() {
+            println!("{}", line);
+        }
+        return;
     }
+    let read_handle = match &args.listen {
+        Some(address) => {
+            let listener = TcpListener::bind(address)
+                .unwrap_or_else(|_| panic!("Could not listen on {}", address));
+            if args.input_format == order_book::format::Format::Jsonl {
+                panic!("Network sessions only support the CSV and BINARY input formats");
+            }
+            let options = network::Options {
+                cancel_on_disconnect: args.cancel_on_disconnect,
+                heartbeat_timeout: args.heartbeat_timeout,
+                format: args.input_format,
+            };
+            thread::spawn(move || network::serve(listener, order_sender, options))
+        }
+        None => {
+            let file = args.file.clone().unwrap();
+            let input_format = args.input_format;
+            thread::spawn(move || {
+                let result = match input_format {
+                    order_book::format::Format::Csv => process_input_orders(&file, order_sender),
+                    order_book::format::Format::Jsonl => {
+                        process_jsonl_input_orders(&file, order_sender).map_err(csv::Error::from)
+                    }
+                    order_book::format::Format::Binary => {
+                        process_binary_input_orders(&file, order_sender).map_err(csv::Error::from)
+                    }
+                };
+                result.unwrap_or_else(|error| {
+                    panic!("Could not read file {}: {}", file.display(), error)
+                })
+            })
+        }
+    };
+    // Print the output while orders are processed, as network sessions never end
+    let event_header = args.event_header;
+    let output_format = args.output_format;
     let output_handle = thread::spawn(move || {
-        while let Ok(data) = output_receiver.recv() {
-            println!("{}", data);
+        let stdout = std::io::stdout();
+        let mut stdout = stdout.lock();
+        while let Ok(event) = output_receiver.recv() {
+            write_event(&mut stdout, &event, output_format, event_header);
         }
     });
+    while let Ok(data) = order_receiver.recv() {
+        order_book.add_order(data);
+    }
+    if let Some(path) = &args.positions {
+        File::create(path)
+            .and_then(|mut file| order_book.positions().write_report(&mut file))
+            .unwrap_or_else(|_| panic!("Could not write file {}", path.display()));
+    }
+    if let Some(path) = &args.statistics {
+        File::create(path)
+            .and_then(|mut file| {
+                order_book
+                    .statistics_report()
+                    .iter()
+                    .try_for_each(|line| writeln!(file, "{}", line))
+            })
+            .unwrap_or_else(|_| panic!("Could not write file {}", path.display()));
+    }
+    if let (Some(path), Some(bars)) = (&args.bars, order_book.bars()) {
+        File::create(path)
+            .and_then(|mut file| bars.write(&mut file, args.bar_format))
+            .unwrap_or_else(|_| panic!("Could not write file {}", path.display()));
+    }
+    if let (Some(path), Some(audit_trail)) = (&args.audit, order_book.audit_trail()) {
+        File::create(path)
+            .and_then(|mut file| audit_trail.write_report(&mut file))
+            .unwrap_or_else(|_| panic!("Could not write file {}", path.display()));
+    }
     // Drop order_book here to implicitly destroy output_sender and let the output thread terminate
     std::mem::drop(order_book);
     output_handle.join().unwrap();
     read_handle.join().unwrap();
 }
 
+/// Write an output event and flush the output, so it is visible immediately
+///
+/// # Args
+/// * `output`: Destination of the output
+/// * `event`: Event to be written
+/// * `format`: Format of the output
+/// * `event_header`: Prefix CSV lines with the sequence number and timestamp of the event
+fn write_event<W: Write>(
+    output: &mut W,
+    event: &order_book::event::Event,
+    format: order_book::format::Format,
+    event_header: bool,
+) {
+    match format {
+        order_book::format::Format::Csv => {
+            writeln!(output, "{}", event.to_csv(event_header)).unwrap()
+        }
+        order_book::format::Format::Jsonl => {
+            writeln!(output, "{}", order_book::jsonl::format_event(event)).unwrap()
+        }
+        order_book::format::Format::Binary => match order_book::binary::encode_event(event) {
+            Ok(encoded) => output.write_all(&encoded).unwrap(),
+            Err(error) => eprintln!("Could not encode event {}: {}", event.sequence, error),
+        },
+    }
+    output.flush().unwrap();
+}
+
 /// Read orders from a provided CSV file and send the content as `order::Order` to another thread,
-/// using the provided `sender`.
+/// using the provided `sender`. Malformed records are reported and skipped.
 ///
 /// # Args
 /// * `path`: Handle for a CSV file containing orders
@@ -54,17 +403,107 @@ fn process_input_orders(
     path: &PathBuf,
     sender: Sender<order_book::order::Order>,
 ) -> Result<(), csv::Error> {
-    let mut reader = csv::ReaderBuilder::new()
-        .comment(Some(b'#'))
-        .has_headers(false)
-        .flexible(true)
-        .trim(csv::Trim::All)
-        .from_path(path)?;
+    let mut reader = order_book::order::csv_reader().from_path(path)?;
     for result in reader.records() {
         let record = result?;
-        sender
-            .send(order_book::order::Order::from(&record))
-            .unwrap();
+        match order_book::order::Order::parse(&record) {
+            Some(order) => sender.send(order).unwrap(),
+            None => eprintln!(
+                "Skipping malformed record: {}",
+                record.iter().collect::<Vec<_>>().join(", ")
+            ),
+        }
+    }
+    Ok(())
+}
+
+/// Read orders from a provided JSON Lines file and send the content as `order::Order` to another
+/// thread, using the provided `sender`. Empty lines are skipped, malformed ones are reported and
+/// skipped.
+///
+/// # Args
+/// * `path`: Handle for a JSON Lines file containing orders
+/// * `sender`: MPSC sender to use for communicating orders
+///
+/// # Return
+/// A `Result` containing a `unit` or an `io::Error`, if there is an issue with reading the
+/// provided file.
+fn process_jsonl_input_orders(
+    path: &PathBuf,
+    sender: Sender<order_book::order::Order>,
+) -> Result<(), std::io::Error> {
+    let reader = BufReader::new(File::open(path)?);
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match order_book::jsonl::parse_line(&line) {
+            Some(order) => sender.send(order).unwrap(),
+            None => eprintln!("Skipping malformed record: {}", line),
+        }
+    }
+    Ok(())
+}
+
+/// Read orders from a provided file in the binary format and send the content as `order::Order`
+/// to another thread, using the provided `sender`. Heartbeats are skipped.
+///
+/// # Args
+/// * `path`: Handle for a binary file containing orders
+/// * `sender`: MPSC sender to use for communicating orders
+///
+/// # Return
+/// A `Result` containing a `unit` or an `io::Error`, if there is an issue with reading the
+/// provided file.
+fn process_binary_input_orders(
+    path: &PathBuf,
+    sender: Sender<order_book::order::Order>,
+) -> Result<(), std::io::Error> {
+    let mut reader = BufReader::new(File::open(path)?);
+    while let Some(message) = order_book::binary::read_message(&mut reader)? {
+        if let order_book::binary::Message::Order(order) = message {
+            sender.send(order).unwrap();
+        }
+    }
+    Ok(())
+}
+
+/// Convert orders from a provided CSV file to the binary format
+///
+/// # Args
+/// * `path`: Handle for a CSV file containing orders
+/// * `output`: Handle for the binary file to write
+///
+/// # Return
+/// A `Result` containing a `unit` or an `csv::Error`, if there is an issue with reading the
+/// provided CSV file, encoding an order or writing the binary file.
+fn convert_input_orders(path: &PathBuf, output: &PathBuf) -> Result<(), csv::Error> {
+    let mut reader = order_book::order::csv_reader().from_path(path)?;
+    let mut writer = BufWriter::new(File::create(output)?);
+    for result in reader.records() {
+        let order = order_book::order::Order::from(&result?);
+        let encoded = order_book::binary::encode_order(&order)
+            .map_err(|error| std::io::Error::new(std::io::ErrorKind::InvalidData, error))?;
+        writer.write_all(&encoded)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Print output events from a provided file in the binary format as lines of the CSV output
+///
+/// # Args
+/// * `path`: Handle for a binary file containing output events
+/// * `event_header`: Prefix every line with the sequence number and timestamp of its event
+///
+/// # Return
+/// A `Result` containing a `unit` or an `io::Error`, if there is an issue with reading the
+/// provided file.
+fn decode_binary_events(path: &PathBuf, event_header: bool) -> Result<(), std::io::Error> {
+    let mut reader = BufReader::new(File::open(path)?);
+    while let Some(event) = order_book::binary::read_event(&mut reader)? {
+        println!("{}", event.to_csv(event_header));
     }
     Ok(())
 }
@@ -72,6 +511,8 @@ fn process_input_orders(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::net::TcpStream;
+    use std::sync::mpsc::Receiver;
 
     #[test]
     fn test_orders_without_trading() {
@@ -354,22 +795,1446 @@ B, S, 11, 100
         assert_eq!(result, output)
     }
 
-    fn process_and_return_output(input: &str, trading: bool) -> String {
+    #[test]
+    fn test_symbols() {
+        let input = "\
+N, 1, IBM, 10, 100, S, 1
+N, 2, MSFT, 11, 100, B, 101
+N, 2, IBM, 10, 100, B, 102
+N, 1, MSFT, 11, 100, S, 2
+F
+";
+
+        let output = "\
+A, 1, 1
+B, S, 10, 100
+A, 2, 101
+B, B, 11, 100
+A, 2, 102
+T, 2, 102, 1, 1, 10, 100
+B, S, -, -
+A, 1, 2
+T, 2, 101, 1, 2, 11, 100
+B, B, -, -
+
+";
+        let result = process_and_return_output(input, true);
+        assert_eq!(result, output)
+    }
+
+    #[test]
+    fn test_call_auction() {
+        let input = "\
+P
+N, 1, IBM, 10, 100, B, 1
+N, 2, IBM, 9, 100, S, 101
+N, 1, IBM, 11, 50, B, 2
+N, 2, IBM, 10, 100, S, 102
+U
+F
+
+P
+N, 1, IBM, 10, 100, B, 1
+N, 2, IBM, 10, 100, S, 101
+C, 2, 101
+U
+F
+";
+
+        let output = "\
+S, PREOPEN
+A, 1, 1
+B, B, 10, 100
+A, 2, 101
+B, S, 9, 100
+I, IBM, 9, 100
+A, 1, 2
+B, B, 11, 50
+A, 2, 102
+I, IBM, 10, 150
+S, OPEN
+I, IBM, -, -
+T, 1, 2, 2, 101, 10, 50
+T, 1, 1, 2, 101, 10, 50
+T, 1, 1, 2, 102, 10, 50
+B, S, 10, 50
+B, B, -, -
+
+S, PREOPEN
+A, 1, 1
+B, B, 10, 100
+A, 2, 101
+B, S, 10, 100
+I, IBM, 10, 100
+A, 2, 101
+B, S, -, -
+I, IBM, -, -
+S, OPEN
+
+";
+        let result = process_and_return_output(input, false);
+        assert_eq!(result, output)
+    }
+
+    #[test]
+    fn test_halted_auction() {
+        let input = "\
+P
+N, 1, IBM, 10, 100, B, 1
+N, 2, IBM, 9, 100, S, 101
+S, HALTED
+S, OPEN
+F
+";
+
+        let output = "\
+S, PREOPEN
+A, 1, 1
+B, B, 10, 100
+A, 2, 101
+B, S, 9, 100
+I, IBM, 9, 100
+S, HALTED
+I, IBM, -, -
+S, OPEN
+T, 1, 1, 2, 101, 9, 100
+B, S, -, -
+B, B, -, -
+
+";
+        let result = process_and_return_output(input, false);
+        assert_eq!(result, output)
+    }
+
+    #[test]
+    fn test_session_states() {
+        let input = "\
+N, 1, IBM, 10, 100, B, 1
+N, 1, IBM, 12, 100, S, 2, GTC
+S, HALTED
+N, 2, IBM, 9, 100, B, 101
+C, 1, 1
+S, OPEN
+N, 2, IBM, 9, 100, B, 102, DAY
+S, CLOSED
+C, 1, 2
+S, HALTED
+S, OPEN
+F
+";
+
+        let output = "\
+A, 1, 1
+B, B, 10, 100
+A, 1, 2
+B, S, 12, 100
+S, HALTED
+R, 2, 101, HALTED
+A, 1, 1
+B, B, -, -
+S, OPEN
+A, 2, 102
+B, B, 9, 100
+S, CLOSED
+E, 2, 102
+B, B, -, -
+R, 1, 2, CLOSED
+S, OPEN
+
+";
+        let result = process_and_return_output(input, false);
+        assert_eq!(result, output)
+    }
+
+    #[test]
+    fn test_price_bands() {
+        let input = "\
+N, 1, IBM, 80, 100, B, 1
+N, 1, IBM, 100, 100, B, 2
+N, 2, IBM, 100, 100, S, 101
+N, 2, IBM, 96, 50, S, 102
+N, 1, IBM, 104, 100, B, 3
+N, 2, IBM, 104, 100, S, 103
+N, 1, IBM, 100, 50, B, 4
+U
+F
+P
+N, 1, IBM, 108, 50, B, 5
+N, 2, IBM, 108, 50, S, 104
+U
+N, 1, IBM, 112, 50, B, 6
+F
+N, 1, IBM, 112, 50, B, 7
+F
+";
+
+        let output = "\
+R, 1, 1, STATIC_BAND
+A, 1, 2
+B, B, 100, 100
+A, 2, 101
+T, 1, 2, 2, 101, 100, 100
+B, B, -, -
+A, 2, 102
+B, S, 96, 50
+A, 1, 3
+B, B, 104, 100
+A, 2, 103
+T, 1, 3, 2, 103, 104, 100
+B, B, -, -
+S, PREOPEN
+A, 1, 4
+B, B, 100, 50
+I, IBM, 100, 50
+S, OPEN
+I, IBM, -, -
+T, 1, 4, 2, 102, 100, 50
+B, S, -, -
+B, B, -, -
+
+S, PREOPEN
+A, 1, 5
+B, B, 108, 50
+A, 2, 104
+B, S, 108, 50
+I, IBM, 108, 50
+S, OPEN
+I, IBM, -, -
+T, 1, 5, 2, 104, 108, 50
+B, S, -, -
+B, B, -, -
+A, 1, 6
+B, B, 112, 50
+
+R, 1, 7, STATIC_BAND
+
+";
+        let (output_sender, output_receiver) = channel();
+        let order_book = order_book::OrderBook::new(output_sender, true).with_price_bands(
+            order_book::price_band::PriceBands {
+                reference_price: Some("100".parse().unwrap()),
+                static_band: Some(1000),
+                dynamic_band: Some(500),
+                volatility_state: order_book::session::State::PreOpen,
+            },
+        );
+        let result = process_with_order_book(input, order_book, output_receiver);
+        assert_eq!(result, output);
+
+        // The circuit breaker may only interrupt continuous trading
+        let parse = order_book::price_band::parse_volatility_state;
+        assert!(parse("HALTED").is_ok());
+        assert!(parse("OPEN").is_err());
+        assert!(parse("CLOSED").is_err());
+    }
+
+    #[test]
+    fn test_instruments() {
+        let config = "\
+I, IBM, 2, 10, 10, 1000
+K, IBM, 0, 0.01
+K, IBM, 10, 0.05
+";
+
+        let input = "\
+N, 1, AAPL, 10, 100, B, 1
+N, 1, IBM, 10.02, 100, B, 2
+N, 1, IBM, 9.995, 100, B, 3
+N, 1, IBM, 9.99, 100, B, 4
+N, 1, IBM, 10.50, 5, S, 5
+N, 1, IBM, 10.50, 2000, S, 6
+N, 1, IBM, 10.50, 15, S, 7
+N, 1, IBM, 10.50, 20, S, 8
+F
+";
+
+        let output = "\
+R, 1, 1, UNKNOWN_SYMBOL
+R, 1, 2, TICK_SIZE
+R, 1, 3, PRICE_SCALE
+A, 1, 4
+B, B, 9.99, 100
+R, 1, 5, MIN_QTY
+R, 1, 6, MAX_QTY
+R, 1, 7, LOT_SIZE
+A, 1, 8
+B, S, 10.5, 20
+
+";
+        let instruments = order_book::instrument::read(config.as_bytes()).unwrap();
         let (output_sender, output_receiver) = channel();
-        let mut order_book = order_book::OrderBook::new(output_sender, trading);
-        let mut reader = csv::ReaderBuilder::new()
-            .comment(Some(b'#'))
-            .has_headers(false)
-            .flexible(true)
-            .trim(csv::Trim::All)
-            .from_reader(input.as_bytes());
+        let order_book =
+            order_book::OrderBook::new(output_sender, false).with_instruments(instruments);
+        let result = process_with_order_book(input, order_book, output_receiver);
+        assert_eq!(result, output);
+
+        let read = |config: &str| order_book::instrument::read(config.as_bytes());
+        assert!(read("I, IBM, 2, 0, 10, 1000").is_err());
+        assert!(read("I, IBM, 2, 10, 1000, 10").is_err());
+        assert!(read("I, IBM, 2, 10, 10, 1000\nK, IBM, 0, 0").is_err());
+        // Malformed records are reported instead of panicking
+        assert!(read("I, IBM, 2, 10").is_err());
+        assert!(read("I, IBM, -2, 10, 10, 1000").is_err());
+        assert!(read("K, IBM, 0, 0.01").is_err());
+        assert!(read("X, IBM").is_err());
+    }
+
+    #[test]
+    fn test_large_quantities() {
+        let input = "\
+N, 4294967296, BTC, 20000, 3000000000, B, 9007199254740993
+N, 4294967297, BTC, 20000, 18446744073709551615, B, 9007199254740994
+N, 4294967297, BTC, 20001, 3000000000, S, 9007199254740995
+F
+";
+
+        let output = "\
+A, 4294967296, 9007199254740993
+B, B, 20000, 3000000000
+A, 4294967297, 9007199254740994
+B, B, 20000, 18446744073709551615
+A, 4294967297, 9007199254740995
+B, S, 20001, 3000000000
+
+";
+        let result = process_and_return_output(input, false);
+        assert_eq!(result, output)
+    }
+
+    #[test]
+    fn test_untrusted_input() {
+        let mut reader = order_book::order::csv_reader().from_reader(
+            "\
+N, 1, IBM, 10, 100, B
+N, 1, IBM, ten, 100, B, 1
+N, 1, IBM, 10, 100, X, 1
+N, 1, IBM, 10, 100, B, 1, GTD:soon
+C, -1, 1
+A, 1, 1, 10
+M, 1, X
+S, LUNCH
+L, 1, 100
+X, 1, 1
+N, 1, IBM, 10, 100, B, 1, @later
+"
+            .as_bytes(),
+        );
         for result in reader.records() {
-            let record = result.unwrap();
-            order_book.add_order(order_book::order::Order::from(&record));
+            assert!(order_book::order::Order::parse(&result.unwrap()).is_none());
         }
+
+        let input = "\
+N, 1, IBM, 10, 0, B, 1
+N, 1, IBM, 10, 100, B, 1
+N, 1, IBM, 9, 100, B, 1
+N, 2, IBM, 9, 100, B, 1
+F
+";
+
+        let output = "\
+R, 1, 1, INVALID_QTY
+A, 1, 1
+B, B, 10, 100
+R, 1, 1, DUPLICATE_ORDER_ID
+A, 2, 1
+
+";
+        let result = process_and_return_output(input, false);
+        assert_eq!(result, output)
+    }
+
+    #[test]
+    fn test_risk_limits() {
+        let config = "\
+L, *, 1000, 50000, 2, 300
+L, 2, -, -, -, -
+";
+
+        let input = "\
+N, 1, IBM, 10, 2000, B, 1
+N, 1, IBM, 100, 600, B, 2
+N, 1, IBM, 10, 100, B, 3
+N, 1, IBM, 9, 100, B, 4
+N, 1, IBM, 8, 100, B, 5
+N, 2, IBM, 10, 100, S, 101
+N, 1, IBM, 8, 200, B, 6
+L, 1, -, -, -, 400
+N, 1, IBM, 8, 200, B, 7
+N, 1, AAPL, 8, 300, B, 8
+A, 1, 4, 9, 2000
+A, 1, 7, 8, 250
+A, 1, 7, 7, 200
+F
+";
+
+        let output = "\
+R, 1, 1, RISK_ORDER_QTY
+R, 1, 2, RISK_NOTIONAL
+A, 1, 3
+B, B, 10, 100
+A, 1, 4
+R, 1, 5, RISK_OPEN_ORDERS
+A, 2, 101
+T, 1, 3, 2, 101, 10, 100
+B, B, 9, 100
+R, 1, 6, RISK_POSITION
+A, 1, 7
+A, 1, 8
+R, 1, 4, RISK_POSITION
+R, 1, 7, RISK_POSITION
+A, 1, 7
+
+";
+        let risk_manager = order_book::risk::read(config.as_bytes()).unwrap();
+        let (output_sender, output_receiver) = channel();
+        let order_book =
+            order_book::OrderBook::new(output_sender, true).with_risk_manager(risk_manager);
+        let result = process_with_order_book(input, order_book, output_receiver);
+        assert_eq!(result, output)
+    }
+
+    #[test]
+    fn test_positions() {
+        let input = "\
+N, 1, IBM, 10, 100, B, 1
+N, 2, IBM, 10, 100, S, 101
+N, 2, IBM, 12, 50, B, 102
+N, 1, IBM, 12, 50, S, 2
+N, 1, IBM, 11, 10, B, 3
+N, 2, IBM, 13.5, 10, S, 103
+";
+
+        let report = "\
+P, 1, IBM, 50, 10, 100, 112.5
+P, 2, IBM, -50, 10, -100, -112.5
+";
+        let (output_sender, _output_receiver) = channel();
+        let mut order_book = order_book::OrderBook::new(output_sender, true);
+        add_orders(&mut order_book, input);
+        let mut result = Vec::new();
+        order_book.positions().write_report(&mut result).unwrap();
+        assert_eq!(String::from_utf8(result).unwrap(), report);
+
+        // The cost of large positions exceeds the quantity times the price in 64 bits
+        let input = "\
+N, 1, IBM, 100, 1000000000000000, B, 1
+N, 2, IBM, 100, 1000000000000000, S, 101
+N, 2, IBM, 101, 1000000000000000, B, 102
+N, 1, IBM, 101, 1000000000000000, S, 2
+";
+        let report = "\
+P, 1, IBM, 0, -, 1000000000000000, -
+P, 2, IBM, 0, -, -1000000000000000, -
+";
+        let (output_sender, _output_receiver) = channel();
+        let mut order_book = order_book::OrderBook::new(output_sender, true);
+        add_orders(&mut order_book, input);
+        let mut result = Vec::new();
+        order_book.positions().write_report(&mut result).unwrap();
+        assert_eq!(String::from_utf8(result).unwrap(), report)
+    }
+
+    #[test]
+    fn test_mass_cancel() {
+        let input = "\
+N, 1, IBM, 10, 100, B, 1
+N, 1, IBM, 9, 100, B, 2
+N, 2, IBM, 9, 50, B, 101
+N, 1, IBM, 12, 100, S, 3
+N, 2, IBM, 13, 100, S, 102
+M, 1, B, *, *
+M, *, *, 12, 13
+M
+F
+";
+
+        let output = "\
+A, 1, 1
+B, B, 10, 100
+A, 1, 2
+A, 2, 101
+A, 1, 3
+B, S, 12, 100
+A, 2, 102
+X, 1, 1
+X, 1, 2
+M, 2
+B, B, 9, 50
+X, 1, 3
+X, 2, 102
+M, 2
+B, S, -, -
+X, 2, 101
+M, 1
+B, B, -, -
+
+";
+        let result = process_and_return_output(input, false);
+        assert_eq!(result, output)
+    }
+
+    #[test]
+    fn test_good_till_date() {
+        let input = "\
+N, 1, IBM, 10, 100, B, 1, GTD:300, @100
+N, 2, IBM, 12, 100, S, 101, GTD:200, @110
+N, 1, IBM, 9, 100, B, 2, GTC, @120
+N, 2, IBM, 13, 100, S, 102, GTD:150, @130
+N, 1, IBM, 11, 100, B, 3, GTD:100, @140
+C, 1, 2, @250
+N, 2, IBM, 11, 50, S, 103, @400
+F
+N, 1, IBM, 10, 100, B, 4, GTD:350, @300
+";
+
+        let output = "\
+A, 1, 1
+B, B, 10, 100
+A, 2, 101
+B, S, 12, 100
+A, 1, 2
+A, 2, 102
+R, 1, 3, EXPIRED
+E, 2, 102
+E, 2, 101
+B, S, -, -
+A, 1, 2
+E, 1, 1
+B, B, -, -
+A, 2, 103
+B, S, 11, 50
+
+R, 1, 4, EXPIRED
+";
+        let result = process_and_return_output(input, false);
+        assert_eq!(result, output)
+    }
+
+    #[test]
+    fn test_event_header() {
+        let input = "\
+N, 1, IBM, 10, 100, B, 1, @100
+N, 2, IBM, 12, 100, S, 101
+N, 1, IBM, 11, 100, B, 2, @90
+C, 2, 101, @200
+F
+";
+
+        let output = "\
+1, 100, A, 1, 1
+2, 100, B, B, 10, 100
+3, 100, A, 2, 101
+4, 100, B, S, 12, 100
+5, 100, A, 1, 2
+6, 100, B, B, 11, 100
+7, 200, A, 2, 101
+8, 200, B, S, -, -
+
+";
+        let (output_sender, output_receiver) = channel();
+        let order_book = order_book::OrderBook::new(output_sender, false);
+        let result = process_with_event_header(input, order_book, output_receiver, true);
+        assert_eq!(result, output)
+    }
+
+    #[test]
+    fn test_trade_tape() {
+        let input = "\
+N, 1, IBM, 10, 100, B, 1, @100
+N, 2, IBM, 10, 100, S, 101, @110
+N, 2, IBM, 12, 100, S, 102
+N, 1, IBM, 12, 100, B, 2, @120
+F
+";
+
+        let output = "\
+A, 1, 1
+B, B, 10, 100
+A, 2, 101
+T, 1, 1, 2, 101, 10, 100
+B, B, -, -
+A, 2, 102
+B, S, 12, 100
+A, 1, 2
+T, 1, 2, 2, 102, 12, 100
+B, S, -, -
+
+";
+        let trade_tape = "\
+1, 110, IBM, 1, 1, 2, 101, 10, 100, S
+2, 120, IBM, 1, 2, 2, 102, 12, 100, B
+";
+        let path = std::env::temp_dir().join("orderbook_test_trade_tape.csv");
+        let (output_sender, output_receiver) = channel();
+        let order_book = order_book::OrderBook::new(output_sender, true)
+            .with_trade_tape(File::create(&path).unwrap());
+        let result = process_with_order_book(input, order_book, output_receiver);
+        assert_eq!(result, output);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), trade_tape)
+    }
+
+    #[test]
+    fn test_tui_records() {
+        let input = "\
+# Comments and blank lines keep the line numbers of the records
+
+N, 1, IBM, 10, 100, B, 1
+N, 1, IBM, 10, 50, B, 2
+N, 1, IBM, 9, 100, B, 3
+N, 2, IBM, 12, 100, S, 101
+";
+        let path = std::env::temp_dir().join("orderbook_test_tui_records.csv");
+        std::fs::write(&path, input).unwrap();
+        let records = tui::load_records(&path, order_book::format::Format::Csv).unwrap();
+        let lines: Vec<u64> = records.iter().map(|record| record.line).collect();
+        assert_eq!(lines, [3, 4, 5, 6]);
+        assert_eq!(records[3].text, "N, 2, IBM, 12, 100, S, 101");
+
+        let (output_sender, _output_receiver) = channel();
+        let mut order_book = order_book::OrderBook::new(output_sender, false);
+        records
+            .into_iter()
+            .for_each(|record| order_book.add_order(record.order));
+        let price = |price: &str| price.parse::<order_book::price::Price>().unwrap();
+        let bids = order_book.depth(&order_book::order::Side::Buy, 5);
+        assert_eq!(bids, [(price("10"), 150, 2), (price("9"), 100, 1)]);
+        let asks = order_book.depth(&order_book::order::Side::Sell, 1);
+        assert_eq!(asks, [(price("12"), 100, 1)]);
+    }
+
+    #[test]
+    fn test_bars() {
+        let input = "\
+N, 1, IBM, 10, 100, B, 1, @100
+N, 2, IBM, 10, 100, S, 101, @105
+N, 1, IBM, 12, 50, B, 2, @110
+N, 2, IBM, 12, 50, S, 102, @120
+N, 2, AAPL, 5, 10, S, 103, @130
+N, 1, AAPL, 5, 10, B, 3, @140
+N, 1, IBM, 11, 30, B, 4, @150
+N, 2, IBM, 11, 30, S, 104, @160
+";
+
+        let csv = "\
+IBM, 105, 120, 10, 12, 10, 12, 150, 10.66666666, 2
+AAPL, 140, 140, 5, 5, 5, 5, 10, 5, 1
+IBM, 160, 160, 11, 11, 11, 11, 30, 11, 1
+";
+        let json = r#"{"symbol":"IBM","start":105,"end":120,"open":"10","high":"12","low":"10","close":"12","volume":150,"vwap":"10.66666666","trades":2}
+{"symbol":"AAPL","start":140,"end":140,"open":"5","high":"5","low":"5","close":"5","volume":10,"vwap":"5","trades":1}
+{"symbol":"IBM","start":160,"end":160,"open":"11","high":"11","low":"11","close":"11","volume":30,"vwap":"11","trades":1}
+"#;
+        let (output_sender, _output_receiver) = channel();
+        let mut order_book = order_book::OrderBook::new(output_sender, true)
+            .with_bars(order_book::bar::Interval::Trades(2));
+        add_orders(&mut order_book, input);
+        let bars = order_book.bars().unwrap();
+        let mut result = Vec::new();
+        bars.write(&mut result, order_book::bar::Format::Csv)
+            .unwrap();
+        assert_eq!(String::from_utf8(result).unwrap(), csv);
+        let mut result = Vec::new();
+        bars.write(&mut result, order_book::bar::Format::Json)
+            .unwrap();
+        assert_eq!(String::from_utf8(result).unwrap(), json)
+    }
+
+    #[test]
+    fn test_statistics() {
+        let input = "\
+N, 1, IBM, 10, 100, B, 1, @0
+N, 2, IBM, 12, 100, S, 101, @10
+N, 1, IBM, 11, 100, B, 2, @20
+N, 2, IBM, 11, 100, S, 102, @40
+C, 1, 1, @50
+N, 1, IBM, 9, 10, B, 3, GTD:10, @60
+Q
+";
+
+        let output = "\
+A, 1, 1
+B, B, 10, 100
+A, 2, 101
+B, S, 12, 100
+A, 1, 2
+B, B, 11, 100
+A, 2, 102
+T, 1, 2, 2, 102, 11, 100
+B, B, 10, 100
+A, 1, 1
+B, B, -, -
+R, 1, 3, EXPIRED
+SU, 1, 3, 1, 1, 1, 0.4762, 1.0000
+SU, 2, 2, 0, 0, 1, 0.5000, 0.0000
+SB, 1.5, 100.00, 100.00
+SM, C, 1
+SM, N, 5
+SM, Q, 1
+";
+        let result = process_and_return_output(input, true);
+        assert_eq!(result, output)
+    }
+
+    #[test]
+    fn test_order_status() {
+        use order_book::status::Status;
+        let input = "\
+N, 1, IBM, 10, 100, B, 1
+N, 1, IBM, 9, 50, B, 2
+N, 2, IBM, 12, 100, S, 101
+N, 2, IBM, 13, 100, S, 102, GTD:200, @100
+N, 2, IBM, 10, 100, S, 103
+N, 2, IBM, 12, 0, S, 105
+N, 2, IBM, 14, 10, S, 101
+C, 1, 2
+N, 1, IBM, 8, 10, B, 3, @300
+N, 1, IBM, 7, 10, B, 4
+M, 1, B, 7, 7
+A, 1, 3, 8, 5
+P
+N, 1, IBM, 12, 60, B, 5
+U
+";
+        let (output_sender, _output_receiver) = channel();
+        let mut order_book = order_book::OrderBook::new(output_sender, true).with_order_tracking();
+        add_orders(&mut order_book, input);
+
+        let price = |price: &str| price.parse::<order_book::price::Price>().unwrap();
+        assert_eq!(order_book.best_bid(), Some((price("8"), 5)));
+        assert_eq!(order_book.best_ask(), Some((price("12"), 40)));
+        let open_orders = |user| {
+            order_book
+                .open_orders(user)
+                .map(|o| o.user_order_id)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(open_orders(1), [3]);
+        assert_eq!(open_orders(2), [101]);
+        let rejected = |reason: Option<&str>| Status::Rejected {
+            reason: reason.map(str::to_string),
+        };
+        let statuses = [
+            (1, 1, Some(Status::Filled)),
+            (2, 103, Some(Status::Filled)),
+            (2, 105, Some(rejected(Some("INVALID_QTY")))),
+            // A rejected duplicate does not affect the resting order, which was filled partially
+            (2, 101, Some(Status::Resting { qty: 40 })),
+            (1, 2, Some(Status::Cancelled)),
+            (2, 102, Some(Status::Expired)),
+            (1, 4, Some(Status::Cancelled)),
+            (1, 3, Some(Status::Resting { qty: 5 })),
+            (1, 5, Some(Status::Filled)),
+            (3, 1, None),
+        ];
+        for (user, user_order_id, status) in statuses {
+            assert_eq!(order_book.order_status(user, user_order_id), status);
+        }
+
+        // A flush keeps the status of closed orders and closes all resting ones
+        add_orders(&mut order_book, "F");
+        assert_eq!(order_book.order_status(1, 1), Some(Status::Filled));
+        assert_eq!(order_book.order_status(1, 3), Some(Status::Flushed));
+        assert_eq!(order_book.order_status(2, 101), Some(Status::Flushed));
+        assert_eq!(order_book.best_bid(), None);
+
+        // Without tracking, only resting orders are known
+        let (output_sender, _output_receiver) = channel();
+        let mut order_book = order_book::OrderBook::new(output_sender, true);
+        add_orders(&mut order_book, input);
+        assert_eq!(
+            order_book.order_status(1, 3),
+            Some(Status::Resting { qty: 5 })
+        );
+        assert_eq!(order_book.order_status(1, 1), None);
+    }
+
+    #[test]
+    fn test_audit_trail() {
+        let input = "\
+N, 1, IBM, 10, 100, B, 1, @100
+N, 2, IBM, 12, 100, S, 101, GTD:300, @110
+N, 2, IBM, 13, 10, S, 101
+A, 1, 1, 10.5, 100, @120
+A, 2, 101, 10.5, 0
+C, 2, 999
+N, 2, IBM, 10.5, 100, S, 102, @130
+P, @140
+N, 1, IBM, 12, 60, B, 3
+U
+N, 1, IBM, 9, 10, B, 4, @400
+M, 1
+N, 1, IBM, 11, 10, B, 5
+F
+N, 1, IBM, 11, 20, B, 5
+";
+
+        let report = "\
+AE, 1, 1, 100, NEW, IBM, B, 10, 100
+AE, 1, 1, 100, ACCEPTED
+AE, 1, 1, 120, AMEND, 10.5, 100
+AE, 1, 1, 120, ACCEPTED
+AE, 1, 1, 130, FILL, 1, 10.5, 100, 0, 2, 102
+AF, 1, 1, FILLED
+AE, 1, 3, 140, NEW, IBM, B, 12, 60
+AE, 1, 3, 140, ACCEPTED
+AE, 1, 3, 140, FILL, 2, 12, 60, 0, 2, 101
+AF, 1, 3, FILLED
+AE, 1, 4, 400, NEW, IBM, B, 9, 10
+AE, 1, 4, 400, ACCEPTED
+AE, 1, 4, 400, MASS_CANCELLED
+AF, 1, 4, CANCELLED
+AE, 1, 5, 400, NEW, IBM, B, 11, 10
+AE, 1, 5, 400, ACCEPTED
+AE, 1, 5, 400, FLUSHED
+AF, 1, 5, FLUSHED
+AE, 1, 5, 400, NEW, IBM, B, 11, 20
+AE, 1, 5, 400, ACCEPTED
+AF, 1, 5, RESTING, 20
+AE, 2, 101, 110, NEW, IBM, S, 12, 100
+AE, 2, 101, 110, ACCEPTED
+AE, 2, 101, 120, AMEND, 10.5, 0
+AE, 2, 101, 120, REJECTED, INVALID_QTY
+AE, 2, 101, 140, FILL, 2, 12, 60, 40, 1, 3
+AE, 2, 101, 400, EXPIRED
+AF, 2, 101, EXPIRED
+AE, 2, 102, 130, NEW, IBM, S, 10.5, 100
+AE, 2, 102, 130, ACCEPTED
+AE, 2, 102, 130, FILL, 1, 10.5, 100, 0, 1, 1
+AF, 2, 102, FILLED
+AE, 2, 999, 120, CANCEL
+AE, 2, 999, 120, ACCEPTED
+";
+        let (output_sender, _output_receiver) = channel();
+        let mut order_book = order_book::OrderBook::new(output_sender, true).with_audit_trail();
+        add_orders(&mut order_book, input);
+        let audit_trail = order_book.audit_trail().unwrap();
+        let mut result = Vec::new();
+        audit_trail.write_report(&mut result).unwrap();
+        assert_eq!(String::from_utf8(result).unwrap(), report);
+
+        let history = audit_trail.history(2, 101).unwrap();
+        assert_eq!(history.entries.len(), 6);
+        assert_eq!(history.status, Some(order_book::status::Status::Expired));
+        assert!(audit_trail.history(3, 1).is_none());
+        // An order that reuses an id after a flush starts a new history
+        let history = audit_trail.history(1, 5).unwrap();
+        assert_eq!(history.entries.len(), 2);
+        assert_eq!(
+            history.status,
+            Some(order_book::status::Status::Resting { qty: 20 })
+        );
+    }
+
+    #[test]
+    fn test_jsonl() {
+        let input = r#"{"type": "new", "user": 1, "symbol": "IBM", "price": "10.5", "qty": 100, "side": "buy", "order_id": 1, "timestamp": 100}
+{"type": "new", "user": 2, "symbol": "IBM", "price": 10.5, "qty": 100, "side": "S", "order_id": 101, "time_in_force": "GTC", "timestamp": 110}
+{"type": "cancel", "user": 1, "order_id": 1}
+{"type": "limits", "user": 1, "max_order_qty": 10}
+{"type": "new", "user": 1, "symbol": "IBM", "price": 9, "qty": 50, "side": "B", "order_id": 2}
+{"type": "flush"}
+"#;
+
+        let output = r#"{"order_id":1,"sequence":1,"timestamp":100,"type":"ack","user":1}
+{"price":"10.5","sequence":2,"side":"B","timestamp":100,"type":"top_of_book","volume":100}
+{"order_id":101,"sequence":3,"timestamp":110,"type":"ack","user":2}
+{"aggressor":"S","buy_order_id":1,"buy_user":1,"price":"10.5","qty":100,"sell_order_id":101,"sell_user":2,"sequence":4,"timestamp":110,"trade_id":1,"type":"trade"}
+{"price":null,"sequence":5,"side":"B","timestamp":110,"type":"top_of_book","volume":null}
+{"order_id":1,"sequence":6,"timestamp":110,"type":"ack","user":1}
+{"order_id":2,"reason":"RISK_ORDER_QTY","sequence":7,"timestamp":110,"type":"reject","user":1}
+{"sequence":8,"timestamp":110,"type":"flush"}
+"#;
+        let (output_sender, output_receiver) = channel();
+        let mut order_book = order_book::OrderBook::new(output_sender, true);
+        for line in input.lines() {
+            order_book.add_order(order_book::jsonl::parse_order(line));
+        }
+        std::mem::drop(order_book);
+        let result: String = output_receiver
+            .iter()
+            .map(|event| order_book::jsonl::format_event(&event) + "\n")
+            .collect();
+        assert_eq!(result, output);
+
+        // Malformed lines of an input file are skipped instead of panicking
+        let read = |name: &str, input: &str| {
+            let path = std::env::temp_dir().join(name);
+            std::fs::write(&path, input).unwrap();
+            let (order_sender, order_receiver) = channel();
+            match name.ends_with(".csv") {
+                true => process_input_orders(&path, order_sender).unwrap(),
+                false => process_jsonl_input_orders(&path, order_sender).unwrap(),
+            }
+            order_receiver.iter().count()
+        };
+        let input = "N, 1, IBM, 10, 100, B, 1\nN, 1, IBM, ten, 100, B, 2\nX\nC, 1, 1\n";
+        assert_eq!(read("orderbook_test_malformed.csv", input), 2);
+        let input = r#"{"type": "cancel", "user": 1, "order_id": 1}
+{"type": "cancel", "user": 1
+{"type": "unknown"}
+{"type": "flush"}
+"#;
+        assert_eq!(read("orderbook_test_malformed.jsonl", input), 2)
+    }
+
+    #[test]
+    fn test_cancel_on_disconnect() {
+        let (output_sender, output_receiver) = channel();
+        let mut order_book = order_book::OrderBook::new(output_sender, false);
+        add_orders(&mut order_book, "N, 1, IBM, 10, 100, B, 1\n");
+        let (order_sender, order_receiver) = channel();
+        let options = network::Options {
+            cancel_on_disconnect: true,
+            heartbeat_timeout: Some(Duration::from_millis(100)),
+            ..network::Options::default()
+        };
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+
+        // First session disconnects
+        let mut client = TcpStream::connect(address).unwrap();
+        client
+            .write_all(b"N, 2, IBM, 9, 100, B, 101\nH\nN, 2, IBM, 12, 100, S, 102\n")
+            .unwrap();
+        std::mem::drop(client);
+        let (stream, _) = listener.accept().unwrap();
+        network::handle_session(stream, 1, &order_sender, &options);
+
+        // Second session stays connected, but misses its heartbeats
+        let mut client = TcpStream::connect(address).unwrap();
+        client.write_all(b"N, 3, IBM, 11, 50, S, 201\n").unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        network::handle_session(stream, 2, &order_sender, &options);
+        std::mem::drop(client);
+
+        // Third session sends a malformed line, fourth one a command reserved for the operator
+        for (session, data) in [
+            (
+                3,
+                &b"N, 4, IBM, 8, 10, B, 301\nN, 4, IBM, ten\nN, 4, IBM, 8, 10, B, 302\n"[..],
+            ),
+            (
+                4,
+                &b"N, 5, IBM, 7, 10, B, 401\nF\nN, 5, IBM, 7, 10, B, 402\n"[..],
+            ),
+        ] {
+            let mut client = TcpStream::connect(address).unwrap();
+            client.write_all(data).unwrap();
+            let (stream, _) = listener.accept().unwrap();
+            network::handle_session(stream, session, &order_sender, &options);
+        }
+
+        // Fifth session may neither cancel nor amend orders it did not enter
+        let mut client = TcpStream::connect(address).unwrap();
+        client
+            .write_all(b"N, 6, IBM, 6, 10, B, 501\nC, 1, 1\nA, 1, 1, 9, 100\nM, *, *, *, *\n")
+            .unwrap();
+        std::mem::drop(client);
+        let (stream, _) = listener.accept().unwrap();
+        network::handle_session(stream, 5, &order_sender, &options);
+
+        std::mem::drop(order_sender);
+        for order in order_receiver {
+            order_book.add_order(order);
+        }
+
+        let output = "\
+A, 1, 1
+B, B, 10, 100
+A, 2, 101
+A, 2, 102
+B, S, 12, 100
+X, 2, 101
+X, 2, 102
+M, 2
+B, S, -, -
+A, 3, 201
+B, S, 11, 50
+X, 3, 201
+M, 1
+B, S, -, -
+A, 4, 301
+X, 4, 301
+M, 1
+A, 5, 401
+X, 5, 401
+M, 1
+A, 6, 501
+R, 1, 1, UNKNOWN_ORDER
+R, 1, 1, UNKNOWN_ORDER
+X, 6, 501
+M, 1
+M, 0
+";
+        let result = process_with_order_book("", order_book, output_receiver);
+        assert_eq!(result, output);
+
+        assert!(network::parse_heartbeat_timeout("0").is_err());
+        assert_eq!(
+            network::parse_heartbeat_timeout("100"),
+            Ok(Duration::from_millis(100))
+        );
+    }
+
+    #[cfg(feature = "http")]
+    #[test]
+    fn test_http() {
+        let server = std::sync::Arc::new(tiny_http::Server::http("127.0.0.1:0").unwrap());
+        let address = server.server_addr().to_ip().unwrap();
+        let (event_sender, event_receiver) = channel();
+        let serving = server.clone();
+        let handle = thread::spawn(move || {
+            let (output_sender, output_receiver) = channel();
+            let order_book = order_book::OrderBook::new(output_sender, true);
+            let output = |event| event_sender.send(event).unwrap();
+            http::Api::new(order_book, output_receiver, output).serve(&serving);
+        });
+        let order = |price: u32, qty: u32, side: &str, order_id: u32| {
+            format!(
+                r#"{{"user": {}, "symbol": "IBM", "price": "{}", "qty": {}, "side": "{}", "order_id": {}}}"#,
+                order_id / 100 + 1,
+                price,
+                qty,
+                side,
+                order_id
+            )
+        };
+        let request = |method: &str, path: &str, body: &str| {
+            let mut stream = TcpStream::connect(address).unwrap();
+            write!(
+                stream,
+                "{} {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\nContent-Length: {}\r\n\r\n{}",
+                method,
+                path,
+                body.len(),
+                body
+            )
+            .unwrap();
+            let mut response = String::new();
+            std::io::Read::read_to_string(&mut stream, &mut response).unwrap();
+            let status = response[9..12].parse::<u16>().unwrap();
+            let (_, body) = response.split_once("\r\n\r\n").unwrap();
+            (
+                status,
+                serde_json::from_str::<serde_json::Value>(body).unwrap(),
+            )
+        };
+
+        let (status, body) = request("POST", "/orders", &order(10, 100, "buy", 1));
+        assert_eq!(status, 201);
+        assert_eq!(body["events"][0]["type"], "ack");
+        assert_eq!(
+            request("POST", "/orders", &order(12, 100, "sell", 101)).0,
+            201
+        );
+        let (status, body) = request("POST", "/orders", &order(11, 100, "buy", 1));
+        assert_eq!(
+            (status, &body["error"]),
+            (409, &"DUPLICATE_ORDER_ID".into())
+        );
+        // A crossing amendment of another quantity rests like a new order when trading
+        let (status, body) = request("PATCH", "/orders/2/101", r#"{"price": "10", "qty": 50}"#);
+        assert_eq!((status, &body["events"][0]["type"]), (200, &"ack".into()));
+        let (status, body) = request("POST", "/orders", &order(10, 0, "sell", 102));
+        assert_eq!((status, &body["error"]), (422, &"INVALID_QTY".into()));
+        let (status, body) = request("POST", "/orders", &order(10, 100, "sell", 103));
+        assert_eq!(status, 201);
+        assert_eq!(body["events"][1]["type"], "trade");
+        assert_eq!(request("POST", "/orders", r#"{"user": 1}"#).0, 400);
+
+        let (status, body) = request("PATCH", "/orders/2/101", r#"{"price": "13", "qty": 50}"#);
+        assert_eq!((status, &body["events"][0]["type"]), (200, &"ack".into()));
+        let (status, body) = request("PATCH", "/orders/2/999", r#"{"price": "13", "qty": 50}"#);
+        assert_eq!((status, &body["error"]), (404, &"UNKNOWN_ORDER".into()));
+        let (status, body) = request("DELETE", "/orders/2/999", "");
+        assert_eq!((status, &body["error"]), (404, &"UNKNOWN_ORDER".into()));
+        assert_eq!(
+            request("POST", "/orders", &order(14, 100, "sell", 104)).0,
+            201
+        );
+        assert_eq!(request("POST", "/orders", &order(9, 100, "buy", 2)).0, 201);
+
+        let (status, body) = request("GET", "/book/IBM?depth=1", "");
+        assert_eq!(status, 200);
+        let expected = serde_json::json!({
+            "symbol": "IBM",
+            "bids": [{"price": "9", "qty": 100, "orders": 1}],
+            "asks": [{"price": "13", "qty": 50, "orders": 1}],
+        });
+        assert_eq!(body, expected);
+        let (_, body) = request("GET", "/book/AAPL", "");
+        assert_eq!(body["asks"], serde_json::json!([]));
+
+        assert_eq!(request("DELETE", "/orders/2/101", "").0, 200);
+        let (_, body) = request("GET", "/book/IBM", "");
+        assert_eq!(body["asks"][0]["price"], "14");
+
+        let (status, body) = request("GET", "/trades", "");
+        assert_eq!(status, 200);
+        let trades = body["trades"].as_array().unwrap();
+        assert_eq!(trades.len(), 1);
+        assert_eq!(trades[0]["buy_order_id"], 1);
+        assert_eq!(trades[0]["sell_order_id"], 103);
+        assert_eq!(request("GET", "/unknown", "").0, 404);
+
+        server.unblock();
+        handle.join().unwrap();
+        // The output of all requests is passed on like the output of an input file
+        assert!(event_receiver
+            .iter()
+            .any(|event| matches!(event.message, order_book::event::Message::Trade { .. })));
+    }
+
+    #[cfg(feature = "websocket")]
+    #[test]
+    fn test_market_data() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let (update_sender, update_receiver) = channel();
+        thread::spawn(move || websocket::serve(listener, update_receiver));
+        let (output_sender, _output_receiver) = channel();
+        let mut order_book =
+            order_book::OrderBook::new(output_sender, true).with_market_data(update_sender);
+        let add = |order_book: &mut order_book::OrderBook, line: &str| {
+            let mut reader = order_book::order::csv_reader().from_reader(line.as_bytes());
+            let record = reader.records().next().unwrap().unwrap();
+            order_book.add_order(order_book::order::Order::from(&record));
+        };
+        add(&mut order_book, "N, 1, IBM, 10, 100, B, 1");
+        add(&mut order_book, "N, 1, IBM, 10, 50, B, 2");
+        add(&mut order_book, "N, 2, AAPL, 20, 100, S, 101");
+
+        let stream = TcpStream::connect(address).unwrap();
+        let (mut socket, _) = tungstenite::client(format!("ws://{}", address), stream).unwrap();
+        let mut send = |request: &str| {
+            socket
+                .send(tungstenite::Message::text(request.to_string()))
+                .unwrap();
+            let message = socket.read().unwrap();
+            serde_json::from_str::<serde_json::Value>(message.to_text().unwrap()).unwrap()
+        };
+        // The updates of the orders so far are applied by another thread, so subscribing again
+        // replaces the subscription until the snapshot contains them
+        let mut snapshot = serde_json::Value::Null;
+        while snapshot["sequence"] != 2 {
+            snapshot = send(r#"{"op": "subscribe", "channel": "depth", "symbol": "IBM"}"#);
+        }
+        let expected = serde_json::json!({
+            "type": "snapshot",
+            "channel": "depth",
+            "symbol": "IBM",
+            "sequence": 2,
+            "bids": [{"price": "10", "qty": 150, "orders": 2}],
+            "asks": [],
+        });
+        assert_eq!(snapshot, expected);
+        let error = send(r#"{"op": "subscribe", "channel": "quotes", "symbol": "IBM"}"#);
+        assert_eq!(error["error"], "MALFORMED_REQUEST");
+        let snapshot = send(r#"{"op": "subscribe", "channel": "trades", "symbol": "IBM"}"#);
+        assert_eq!(snapshot["trades"], serde_json::json!([]));
+        assert_eq!(snapshot["sequence"], 0);
+
+        add(&mut order_book, "N, 2, AAPL, 21, 100, S, 102");
+        add(&mut order_book, "N, 2, IBM, 10, 100, S, 103");
+        let mut messages = Vec::new();
+        while messages.len() < 2 {
+            let message = socket.read().unwrap();
+            messages.push(
+                serde_json::from_str::<serde_json::Value>(message.to_text().unwrap()).unwrap(),
+            );
+        }
+        // Updates of other symbols are not sent, and every channel has its own sequence
+        assert_eq!(messages[0]["channel"], "trades");
+        assert_eq!(messages[0]["sequence"], 1);
+        assert_eq!(messages[0]["price"], "10");
+        assert_eq!(messages[0]["qty"], 100);
+        assert_eq!(messages[0]["aggressor"], "sell");
+        let expected = serde_json::json!({
+            "type": "update",
+            "channel": "depth",
+            "symbol": "IBM",
+            "sequence": 3,
+            "side": "buy",
+            "price": "10",
+            "qty": 50,
+            "orders": 1,
+        });
+        assert_eq!(messages[1], expected);
+
+        // The top of book is published per symbol, although the best ask of the book is AAPL
+        add(&mut order_book, "N, 2, IBM, 30, 10, S, 104");
+        let update = socket.read().unwrap();
+        let update = serde_json::from_str::<serde_json::Value>(update.to_text().unwrap()).unwrap();
+        assert_eq!(
+            (&update["sequence"], &update["price"]),
+            (&4.into(), &"30".into())
+        );
+        let mut send = |request: &str| {
+            socket
+                .send(tungstenite::Message::text(request.to_string()))
+                .unwrap();
+            let message = socket.read().unwrap();
+            serde_json::from_str::<serde_json::Value>(message.to_text().unwrap()).unwrap()
+        };
+        let mut snapshot = serde_json::Value::Null;
+        while snapshot["ask"].is_null() {
+            snapshot = send(r#"{"op": "subscribe", "channel": "top_of_book", "symbol": "IBM"}"#);
+        }
+        assert_eq!(
+            snapshot["bid"],
+            serde_json::json!({"price": "10", "qty": 50})
+        );
+        assert_eq!(
+            snapshot["ask"],
+            serde_json::json!({"price": "30", "qty": 10})
+        );
+    }
+
+    #[test]
+    fn test_binary() {
+        let input = "\
+N, 1, IBM, 10, 100, B, 1, @100
+N, 2, IBM, 12, 100, S, 101, GTC, @110
+A, 1, 1, 10.5, 100, @120
+A, 2, 101, 10.5, 100
+N, 2, IBM, 10.5, 100, S, 102
+F
+Q
+";
+
+        let output = "\
+1, 100, A, 1, 1
+2, 100, B, B, 10, 100
+3, 110, A, 2, 101
+4, 110, B, S, 12, 100
+5, 120, A, 1, 1
+6, 120, B, B, 10.5, 100
+7, 120, A, 2, 101
+8, 120, T, 1, 1, 2, 101, 10.5, 100
+9, 120, B, S, -, -
+10, 120, B, B, -, -
+11, 120, A, 2, 102
+12, 120, B, S, 10.5, 100
+
+14, 120, SU, 1, 1, 0, 0, 1, 1.0000, 0.0000
+15, 120, SU, 2, 2, 0, 0, 1, 0.5000, 0.0000
+16, 120, SB, 2, 100.00, 100.00
+17, 120, SM, A, 2
+18, 120, SM, F, 1
+19, 120, SM, N, 3
+20, 120, SM, Q, 1
+";
+        // Convert the CSV input to the binary format and read it back like an input file, then
+        // convert the output events to the binary format and read them back
+        let process_binary = |input: &str| {
+            let mut encoded = Vec::new();
+            let mut reader = order_book::order::csv_reader().from_reader(input.as_bytes());
+            for result in reader.records() {
+                let order = order_book::order::Order::from(&result.unwrap());
+                encoded.extend(order_book::binary::encode_order(&order).unwrap());
+            }
+            let (output_sender, output_receiver) = channel();
+            let mut order_book = order_book::OrderBook::new(output_sender, true);
+            let mut encoded = encoded.as_slice();
+            while let Some(message) = order_book::binary::read_message(&mut encoded).unwrap() {
+                if let order_book::binary::Message::Order(order) = message {
+                    order_book.add_order(order);
+                }
+            }
+            std::mem::drop(order_book);
+
+            let mut events = Vec::new();
+            for event in output_receiver {
+                events.extend(order_book::binary::encode_event(&event).unwrap());
+            }
+            let mut events = events.as_slice();
+            let mut result = String::new();
+            while let Some(event) = order_book::binary::read_event(&mut events).unwrap() {
+                result += &event.to_csv(true);
+                result += "\n";
+            }
+            result
+        };
+        assert_eq!(process_binary(input), output);
+
+        // A message cut off by the end of the input is reported as malformed
+        let record = csv::StringRecord::from(vec!["N", "1", "IBM", "10", "100", "B", "1"]);
+        let encoded = order_book::binary::encode_order(&order_book::order::Order::from(&record));
+        let mut truncated = &encoded.unwrap()[..20];
+        let error = order_book::binary::read_message(&mut truncated)
+            .err()
+            .unwrap();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+
+        // Commands give the same output as in the CSV format
+        let input = "\
+N, 1, IBM, 10, 100, B, 1
+P
+N, 2, IBM, 9, 100, S, 101
+U
+L, 1, 50, -, -, -
+N, 1, IBM, 8, 100, B, 2
+L, *, -, 1000, -, 10
+S, HALTED
+M, *, B, 5, 20
+S, OPEN
+N, 3, IBM, 8, 100, B, 301
+M, 3, *, *, *
+N, 4, IBM, 7, 18446744073709551615, B, 18446744073709551615
+Q
+";
+        let (output_sender, output_receiver) = channel();
+        let order_book = order_book::OrderBook::new(output_sender, true);
+        let output = process_with_event_header(input, order_book, output_receiver, true);
+        assert_eq!(process_binary(input), output);
+    }
+
+    #[test]
+    fn test_synthetic_order_flow() {
+        let config = generator::Config {
+            seed: 42,
+            price: "EXPONENTIAL:3".parse().unwrap(),
+            size: "UNIFORM:1-10".parse().unwrap(),
+            cancel_ratio: 0.4,
+            aggressive_ratio: 0.2,
+        };
+        let generate = |count| -> String {
+            generator::Generator::new(config.clone())
+                .take(count)
+                .map(|order| generator::to_csv(&order) + "\n")
+                .collect()
+        };
+        // The same seed generates the same orders
+        let input = generate(1000);
+        assert_eq!(input, generate(1000));
+
+        // Cancellations address resting orders and aggressive orders trade
+        let output = process_and_return_output(&input, true);
+        assert!(!output.lines().any(|line| line.starts_with('R')));
+        assert!(output.lines().any(|line| line.starts_with('T')));
+
+        let (output_sender, output_receiver) = channel();
+        let mut order_book = order_book::OrderBook::new(output_sender, true);
+        let orders = generator::Generator::new(config).take(1000).collect();
+        let report = bench::run(&mut order_book, orders);
+        std::mem::drop(order_book);
+        assert_eq!(output_receiver.iter().count(), output.lines().count());
+        let lines = report.lines();
+        assert!(lines[0].starts_with("BO, 1000, "));
+        let latencies: Vec<u64> = ["p50", "p99", "p99.9", "max"]
+            .iter()
+            .zip(&lines[1..5])
+            .map(|(name, line)| {
+                let (prefix, latency) = line.rsplit_once(", ").unwrap();
+                assert_eq!(prefix, format!("BL, {}", name));
+                latency.parse().unwrap()
+            })
+            .collect();
+        assert!(latencies.windows(2).all(|pair| pair[0] <= pair[1]));
+        let histogram: u64 = lines[5..]
+            .iter()
+            .map(|line| line.rsplit_once(", ").unwrap().1.parse::<u64>().unwrap())
+            .sum();
+        assert_eq!(histogram, 1000);
+    }
+
+    #[test]
+    fn test_random_order_streams() {
+        // The invariant checker runs after every message and panics on a violation
+        for seed in 0..200 {
+            let input = random_order_stream(seed, 300);
+            for trading in [false, true] {
+                let (output_sender, output_receiver) = channel();
+                let mut order_book =
+                    order_book::OrderBook::new(output_sender, trading).with_invariant_checks();
+                add_orders(&mut order_book, &input);
+                std::mem::drop(order_book);
+                assert!(output_receiver.iter().count() > 0);
+            }
+        }
+    }
+
+    /// Generate a stream of random CSV input records of all kinds, with few symbols, prices, users
+    /// and quantities so that orders trade, cancellations hit and auctions uncross
+    fn random_order_stream(seed: u64, len: usize) -> String {
+        use rand::rngs::StdRng;
+        use rand::{Rng, SeedableRng};
+
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut input = String::new();
+        let mut time = 0;
+        for id in 1..=len {
+            let user = rng.gen_range(1..=3);
+            let side = if rng.gen_bool(0.5) { "B" } else { "S" };
+            let symbol = if rng.gen_bool(0.8) { "IBM" } else { "MSFT" };
+            let price = rng.gen_range(95..=105);
+            let qty = rng.gen_range(1..=4) * 10;
+            let known_id = rng.gen_range(1..=id);
+            let record = match rng.gen_range(0..100) {
+                0..=49 => {
+                    let time_in_force = match rng.gen_range(0..3) {
+                        0 => "DAY".to_string(),
+                        1 => "GTC".to_string(),
+                        _ => format!("GTD:{}", time + rng.gen_range(0..50)),
+                    };
+                    format!(
+                        "N, {}, {}, {}, {}, {}, {}, {}",
+                        user, symbol, price, qty, side, id, time_in_force
+                    )
+                }
+                50..=64 => format!("C, {}, {}", user, known_id),
+                65..=79 => format!("A, {}, {}, {}, {}", user, known_id, price, qty),
+                80..=84 => format!("M, {}, {}, *, {}", user, side, price),
+                85..=88 => "P".to_string(),
+                89..=93 => "U".to_string(),
+                94..=95 => "S, HALTED".to_string(),
+                96..=97 => "S, CLOSED".to_string(),
+                98 => "S, OPEN".to_string(),
+                _ => "F".to_string(),
+            };
+            input += &record;
+            if rng.gen_bool(0.3) {
+                time += rng.gen_range(1..=10);
+                input += &format!(", @{}", time);
+            }
+            input += "\n";
+        }
+        input
+    }
+
+    fn process_and_return_output(input: &str, trading: bool) -> String {
+        let (output_sender, output_receiver) = channel();
+        let order_book = order_book::OrderBook::new(output_sender, trading).with_invariant_checks();
+        process_with_order_book(input, order_book, output_receiver)
+    }
+
+    fn add_orders(order_book: &mut order_book::OrderBook, input: &str) {
+        let mut reader = order_book::order::csv_reader().from_reader(input.as_bytes());
+        for result in reader.records() {
+            let record = result.unwrap();
+            order_book.add_order(order_book::order::Order::from(&record));
+        }
+    }
+
+    fn process_with_order_book(
+        input: &str,
+        order_book: order_book::OrderBook,
+        output_receiver: Receiver<order_book::event::Event>,
+    ) -> String {
+        process_with_event_header(input, order_book, output_receiver, false)
+    }
+
+    fn process_with_event_header(
+        input: &str,
+        mut order_book: order_book::OrderBook,
+        output_receiver: Receiver<order_book::event::Event>,
+        event_header: bool,
+    ) -> String {
+        add_orders(&mut order_book, input);
         let output_handle = thread::spawn(move || -> String {
             let mut result = String::new();
-            while let Ok(data) = output_receiver.recv() {
+            while let Ok(event) = output_receiver.recv() {
+                let data = event.to_csv(event_header);
                 result += &data;
                 result += "\n";
                 println!("{data}");
@@ -0,0 +1,137 @@
+//! Defines network sessions that receive orders over TCP
+
+use crate::order_book::binary::{self, Message};
+use crate::order_book::format::Format;
+use crate::order_book::order::{self, Order};
+use std::io::BufReader;
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::Sender;
+use std::thread;
+use std::time::Duration;
+
+/// Options that apply to all network sessions
+#[derive(Clone)]
+pub struct Options {
+    /// Cancel all resting orders of a session when it disconnects or misses heartbeats
+    pub cancel_on_disconnect: bool,
+    /// Time a session may stay silent before it is disconnected, `None` to never disconnect
+    pub heartbeat_timeout: Option<Duration>,
+    /// Format the sessions send their orders in, either CSV or binary
+    pub format: Format,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            cancel_on_disconnect: false,
+            heartbeat_timeout: None,
+            format: Format::Csv,
+        }
+    }
+}
+
+/// Accept network sessions and handle every session in its own thread. Sessions are numbered in
+/// the order they connect, starting at 1.
+///
+/// # Args
+/// - `listener`: Listener to accept sessions from
+/// - `sender`: MPSC sender to use for communicating orders
+/// - `options`: Options that apply to all sessions
+pub fn serve(listener: TcpListener, sender: Sender<Order>, options: Options) {
+    for (session, stream) in (1..).zip(listener.incoming()) {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(_) => continue,
+        };
+        let sender = sender.clone();
+        let options = options.clone();
+        thread::spawn(move || handle_session(stream, session, &sender, &options));
+    }
+}
+
+/// Read orders from a network session in the same format as an input file and send them to
+/// another thread. A session sends heartbeats, `H` in the CSV and binary format, to stay connected
+/// while it has no orders to send. Returns when the session disconnects, sends malformed data, sends
+/// a command that is reserved for the operator or misses its heartbeats.
+///
+/// # Args
+/// - `stream`: Connection of the session
+/// - `session`: Identifier the orders of the session are tagged with
+/// - `sender`: MPSC sender to use for communicating orders
+/// - `options`: Options that apply to all sessions
+pub fn handle_session(stream: TcpStream, session: u64, sender: &Sender<Order>, options: &Options) {
+    if stream.set_read_timeout(options.heartbeat_timeout).is_err() {
+        return;
+    }
+    // A read that times out ends the session like a disconnect
+    // Returns `false` for commands the session may not send, which end the session
+    let send = |mut order: Order| {
+        if is_operator_command(&order) {
+            return false;
+        }
+        // Sessions may only cancel and amend their own orders
+        order.session = Some(session);
+        if let order::Kind::MassCancel(filter) = &mut order.kind {
+            filter.session = Some(session);
+        }
+        sender.send(order).unwrap();
+        true
+    };
+    match options.format {
+        Format::Binary => {
+            let mut reader = BufReader::new(stream);
+            while let Ok(Some(message)) = binary::read_message(&mut reader) {
+                if let Message::Order(order) = message {
+                    if !send(order) {
+                        break;
+                    }
+                }
+            }
+        }
+        Format::Csv | Format::Jsonl => {
+            let mut reader = order::csv_reader().from_reader(stream);
+            for result in reader.records() {
+                let record = match result {
+                    Ok(record) => record,
+                    Err(_) => break,
+                };
+                if record.get(0) == Some("H") {
+                    continue;
+                }
+                if !Order::parse(&record).is_some_and(send) {
+                    break;
+                }
+            }
+        }
+    }
+    if options.cancel_on_disconnect {
+        sender
+            .send(Order::new_session_cancellation(session))
+            .unwrap();
+    }
+}
+
+/// Interpret the heartbeat timeout in milliseconds. A timeout of zero is refused, as a session
+/// could never stay connected.
+///
+/// # Args
+/// - `millis`: Timeout in milliseconds
+///
+/// # Return
+/// - The timeout or an error if it is not a positive integer
+pub fn parse_heartbeat_timeout(millis: &str) -> Result<Duration, String> {
+    match millis.parse::<u64>() {
+        Ok(0) => Err("Heartbeat timeout must be positive".to_string()),
+        Ok(millis) => Ok(Duration::from_millis(millis)),
+        Err(error) => Err(error.to_string()),
+    }
+}
+
+/// Checks if an order is a command that only the operator may send, i.e. a flush, a session state
+/// transition or a change of risk limits
+fn is_operator_command(order: &Order) -> bool {
+    matches!(
+        order.kind,
+        order::Kind::Flush | order::Kind::Transition(_) | order::Kind::Limits(..)
+    )
+}
@@ -1,21 +1,78 @@
 //! Module that defines data structures and functions around an Orderbook.
 
-use std::collections::BTreeMap;
+use price::Price;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet, BinaryHeap, HashMap};
+use std::io::Write;
 use std::sync::mpsc::Sender;
 
+pub mod audit;
+pub mod bar;
+pub mod binary;
+pub mod event;
+pub mod format;
+pub mod instrument;
+pub mod invariant;
+pub mod jsonl;
+pub mod market_data;
 pub mod order;
+pub mod position;
+pub mod price;
+pub mod price_band;
+pub mod risk;
+pub mod session;
+pub mod statistics;
+pub mod status;
 
 /// Struct to represent one order book consisting of an ask book and bid book. Every book stores a
 /// collection of `Order`s for a given price value.
 pub struct OrderBook {
     /// Contains seller orders
-    ask_book: BTreeMap<i32, Vec<order::Order>>,
+    ask_book: BTreeMap<Price, Vec<order::Order>>,
     /// Contains buyer orders
-    bid_book: BTreeMap<i32, Vec<order::Order>>,
-    lowest_ask: Option<(i32, i32)>,
-    highest_bid: Option<(i32, i32)>,
-    log_sender: Sender<String>,
+    bid_book: BTreeMap<Price, Vec<order::Order>>,
+    /// Side and price level of every resting order by user and order id
+    resting: HashMap<(u64, u64), (order::Side, Price)>,
+    /// Price levels the current message changed, by symbol, side and price
+    touched: BTreeSet<(String, order::Side, Price)>,
+    lowest_ask: Option<(Price, u64)>,
+    highest_bid: Option<(Price, u64)>,
+    events: event::Publisher,
     match_orders: bool,
+    state: session::State,
+    /// Last published indicative price and volume per symbol during a call phase
+    indicative: HashMap<String, (Price, u64)>,
+    /// Price of the last trade, used as reference price when uncrossing the book
+    last_trade_price: Option<Price>,
+    price_bands: price_band::PriceBands,
+    /// Price of the last uncross per symbol, which replaces the configured reference price of the
+    /// static band
+    auction_prices: HashMap<String, Price>,
+    /// Reference data by symbol, new orders for unknown symbols are rejected unless it is empty
+    instruments: HashMap<String, instrument::Instrument>,
+    risk_manager: risk::RiskManager,
+    positions: position::Ledger,
+    /// Logical clock, advanced by the event timestamps of the input records
+    clock: u64,
+    /// Expiry timestamps of resting good-till-date orders. May contain expiries of orders that
+    /// already left the book.
+    expiries: BinaryHeap<Reverse<u64>>,
+    /// Identifier of the next trade, unique over the lifetime of the order book
+    next_trade_id: u64,
+    /// Destination of the trade tape, which lists every trade in a dedicated format
+    trade_tape: Option<Box<dyn Write>>,
+    /// Aggregates trades into bars, if configured
+    bars: Option<bar::Aggregator>,
+    statistics: statistics::Statistics,
+    /// Publishes market data per symbol, if configured
+    market_data: Option<market_data::Feed>,
+    /// Final status of the orders that left the book or were rejected, by user and order id, if
+    /// configured
+    closed_orders: Option<HashMap<(u64, u64), status::Status>>,
+    /// Records the lifecycle of every order, if configured
+    audit_trail: Option<audit::Trail>,
+    /// Checks the invariants of the book after every message, if configured
+    invariants: Option<invariant::Checker>,
 }
 
 impl OrderBook {
@@ -26,26 +83,353 @@ impl OrderBook {
     ///
     /// # Return
     /// A new `OrderBook` instance
-    pub fn new(output_sender: Sender<String>, match_orders: bool) -> OrderBook {
+    pub fn new(output_sender: Sender<event::Event>, match_orders: bool) -> OrderBook {
         OrderBook {
             ask_book: BTreeMap::new(),
             bid_book: BTreeMap::new(),
+            resting: HashMap::new(),
+            touched: BTreeSet::new(),
             lowest_ask: None,
             highest_bid: None,
-            log_sender: output_sender,
+            events: event::Publisher::new(output_sender),
             match_orders,
+            state: session::State::Open,
+            indicative: HashMap::new(),
+            last_trade_price: None,
+            price_bands: price_band::PriceBands::default(),
+            auction_prices: HashMap::new(),
+            instruments: HashMap::new(),
+            risk_manager: risk::RiskManager::default(),
+            positions: position::Ledger::default(),
+            clock: 0,
+            expiries: BinaryHeap::new(),
+            next_trade_id: 1,
+            trade_tape: None,
+            bars: None,
+            statistics: statistics::Statistics::default(),
+            market_data: None,
+            closed_orders: None,
+            audit_trail: None,
+            invariants: None,
         }
     }
 
+    /// Configure price bands for the order book
+    ///
+    /// # Args
+    /// - `price_bands`: Price bands new orders and trades are checked against
+    ///
+    /// # Return
+    /// The `OrderBook` instance using the given price bands
+    pub fn with_price_bands(mut self, price_bands: price_band::PriceBands) -> OrderBook {
+        self.price_bands = price_bands;
+        self
+    }
+
+    /// Configure instrument reference data for the order book
+    ///
+    /// # Args
+    /// - `instruments`: Instruments by symbol new orders are validated against
+    ///
+    /// # Return
+    /// The `OrderBook` instance using the given instruments
+    pub fn with_instruments(
+        mut self,
+        instruments: HashMap<String, instrument::Instrument>,
+    ) -> OrderBook {
+        self.instruments = instruments;
+        self
+    }
+
+    /// Configure pre-trade risk checks for the order book
+    ///
+    /// # Args
+    /// - `risk_manager`: Risk manager holding the limits new orders are checked against
+    ///
+    /// # Return
+    /// The `OrderBook` instance using the given risk manager
+    pub fn with_risk_manager(mut self, risk_manager: risk::RiskManager) -> OrderBook {
+        self.risk_manager = risk_manager;
+        self
+    }
+
+    /// Select the clock the output events are timestamped with
+    ///
+    /// # Args
+    /// - `clock`: Logical clock of the order book or wall clock
+    ///
+    /// # Return
+    /// The `OrderBook` instance using the given clock
+    pub fn with_clock(mut self, clock: event::Clock) -> OrderBook {
+        self.events.set_clock(clock);
+        self
+    }
+
+    /// Configure a trade tape for the order book. Every trade is written to the tape in the CSV
+    /// format
+    /// `trade_id, timestamp, symbol, buyer_user_id, buyer_order_id, seller_user_id,
+    /// seller_order_id, price, quantity, aggressor_side`.
+    ///
+    /// # Args
+    /// - `trade_tape`: Destination of the trade tape
+    ///
+    /// # Return
+    /// The `OrderBook` instance writing the given trade tape
+    pub fn with_trade_tape<W: Write + 'static>(mut self, trade_tape: W) -> OrderBook {
+        self.trade_tape = Some(Box::new(trade_tape));
+        self
+    }
+
+    /// Configure the aggregation of trades into bars for the order book
+    ///
+    /// # Args
+    /// - `interval`: Interval that determines when a bar is completed
+    ///
+    /// # Return
+    /// The `OrderBook` instance aggregating its trades into bars
+    pub fn with_bars(mut self, interval: bar::Interval) -> OrderBook {
+        self.bars = Some(bar::Aggregator::new(interval));
+        self
+    }
+
+    /// Configure the publication of market data for the order book
+    ///
+    /// # Args
+    /// - `sender`: A mpsc sender the changes of the top of book, the price levels and the trades
+    ///   are sent to
+    ///
+    /// # Return
+    /// The `OrderBook` instance publishing market data
+    pub fn with_market_data(mut self, sender: Sender<market_data::Update>) -> OrderBook {
+        self.market_data = Some(market_data::Feed::new(sender));
+        self
+    }
+
+    /// Configure the order book to check its invariants after every message and panic on a
+    /// violation, see `invariant::Checker`. Meant for tests and fuzzing, as the check searches the
+    /// whole book.
+    ///
+    /// # Return
+    /// The `OrderBook` instance checking its invariants
+    pub fn with_invariant_checks(mut self) -> OrderBook {
+        self.invariants = Some(invariant::Checker::default());
+        self
+    }
+
+    /// Configure the order book to track the final status of every order that left the book or
+    /// was rejected, which `order_status` reports. The statuses are kept for the lifetime of the
+    /// order book, so memory grows by one entry per order.
+    ///
+    /// # Return
+    /// The `OrderBook` instance tracking the status of its orders
+    pub fn with_order_tracking(mut self) -> OrderBook {
+        self.closed_orders = Some(HashMap::new());
+        self
+    }
+
+    /// Configure an audit trail for the order book, which records the lifecycle of every order
+    ///
+    /// # Return
+    /// The `OrderBook` instance recording an audit trail
+    pub fn with_audit_trail(mut self) -> OrderBook {
+        self.audit_trail = Some(audit::Trail::default());
+        self
+    }
+
+    /// Get the audit trail of the order book, if configured
+    pub fn audit_trail(&self) -> Option<&audit::Trail> {
+        self.audit_trail.as_ref()
+    }
+
+    /// Get the bars aggregated from the trades of the order book, if configured
+    pub fn bars(&self) -> Option<&bar::Aggregator> {
+        self.bars.as_ref()
+    }
+
+    /// Get a report of the statistics collected over the lifetime of the order book, see
+    /// `statistics::Statistics::report`
+    pub fn statistics_report(&self) -> Vec<event::Message> {
+        self.statistics.report()
+    }
+
+    /// Get the price levels of one side of the book, starting at the best price
+    ///
+    /// # Args
+    /// - `side`: Side of the book
+    /// - `levels`: Maximum number of levels
+    ///
+    /// # Return
+    /// - The price, total quantity and number of orders of every level
+    pub fn depth(&self, side: &order::Side, levels: usize) -> Vec<(Price, u64, usize)> {
+        let level = |(price, orders): (&Price, &Vec<order::Order>)| {
+            (*price, total_qty(orders), orders.len())
+        };
+        match side {
+            order::Side::Buy => self.bid_book.iter().rev().take(levels).map(level).collect(),
+            order::Side::Sell => self.ask_book.iter().take(levels).map(level).collect(),
+        }
+    }
+
+    /// Iterate over the resting orders of one side of the book, starting at the best price and in
+    /// time priority within a price level
+    ///
+    /// # Args
+    /// - `side`: Side of the book
+    pub fn orders(&self, side: &order::Side) -> Box<dyn Iterator<Item = &order::Order> + '_> {
+        match side {
+            order::Side::Buy => Box::new(self.bid_book.values().rev().flatten()),
+            order::Side::Sell => Box::new(self.ask_book.values().flatten()),
+        }
+    }
+
+    /// Get the best bid of the book
+    ///
+    /// # Return
+    /// - The price and total quantity of the highest bid level, `None` if there are no bids
+    pub fn best_bid(&self) -> Option<(Price, u64)> {
+        self.highest_bid
+    }
+
+    /// Get the best ask of the book
+    ///
+    /// # Return
+    /// - The price and total quantity of the lowest ask level, `None` if there are no asks
+    pub fn best_ask(&self) -> Option<(Price, u64)> {
+        self.lowest_ask
+    }
+
+    /// Iterate over the resting orders of one user, the bids first. Takes O(n) time in the number
+    /// of resting orders.
+    ///
+    /// # Args
+    /// - `user`: User whose orders are listed
+    pub fn open_orders(&self, user: u64) -> impl Iterator<Item = &order::Order> + '_ {
+        self.orders(&order::Side::Buy)
+            .chain(self.orders(&order::Side::Sell))
+            .filter(move |o| o.user == user)
+    }
+
+    /// Look up the status of an order. Orders that rest in the book are searched in O(n) time in
+    /// the number of orders at their price level, all others in constant time. The status of
+    /// orders that left the book or were rejected is only known with `with_order_tracking`.
+    ///
+    /// # Args
+    /// - `user`: User of the order
+    /// - `user_order_id`: Order id assigned by the user
+    ///
+    /// # Return
+    /// - The status of the order, `None` if the order is unknown
+    pub fn order_status(&self, user: u64, user_order_id: u64) -> Option<status::Status> {
+        let resting = self
+            .resting
+            .get(&(user, user_order_id))
+            .and_then(|(side, price)| {
+                let book = match side {
+                    order::Side::Buy => &self.bid_book,
+                    order::Side::Sell => &self.ask_book,
+                };
+                book.get(price)?
+                    .iter()
+                    .find(|o| o.user == user && o.user_order_id == user_order_id)
+            });
+        match resting {
+            Some(order) => Some(status::Status::Resting { qty: order.qty }),
+            None => self
+                .closed_orders
+                .as_ref()?
+                .get(&(user, user_order_id))
+                .cloned(),
+        }
+    }
+
+    /// Get the positions of all users resulting from the trades of the order book
+    pub fn positions(&self) -> &position::Ledger {
+        &self.positions
+    }
+
     /// Add an order to the order book
     ///
     /// # Args
     /// - `order`: Order to be added
     pub fn add_order(&mut self, order: order::Order) {
+        let message = self.invariants.as_ref().map(|_| order.clone());
+        self.process(order);
+        let touched = std::mem::take(&mut self.touched);
+        let symbols: BTreeSet<&str> = touched.iter().map(|(s, _, _)| s.as_str()).collect();
+        for symbol in symbols {
+            self.update_mark(symbol);
+            if self.state == session::State::PreOpen {
+                self.update_indicative(symbol);
+            }
+        }
+        if let Some(feed) = &mut self.market_data {
+            let (bid_book, ask_book) = (&self.bid_book, &self.ask_book);
+            feed.publish(&touched, |symbol, side, price| {
+                let book = match side {
+                    order::Side::Buy => bid_book,
+                    order::Side::Sell => ask_book,
+                };
+                let orders = book.get(&price).into_iter().flatten();
+                orders
+                    .filter(|o| o.symbol == symbol)
+                    .fold((0u64, 0), |(qty, count), o| {
+                        (qty.saturating_add(o.qty), count + 1)
+                    })
+            });
+        }
+        if let (Some(mut checker), Some(message)) = (self.invariants.take(), message) {
+            if let Err(violation) = checker.check(self, &message) {
+                panic!("Invariant violated: {}", violation);
+            }
+            self.invariants = Some(checker);
+        }
+    }
+
+    /// Process an incoming message of any kind
+    ///
+    /// # Args
+    /// - `order`: Message to be processed
+    fn process(&mut self, order: order::Order) {
+        if let Some(timestamp) = order.timestamp {
+            self.advance_clock(timestamp);
+        }
+        let received = match order.kind {
+            order::Kind::New => Some(audit::Action::NewReceived {
+                symbol: order.symbol.clone(),
+                side: order.side.clone(),
+                price: order.price,
+                qty: order.qty,
+            }),
+            order::Kind::Cancel => Some(audit::Action::CancelReceived),
+            order::Kind::Amend => Some(audit::Action::AmendReceived {
+                price: order.price,
+                qty: order.qty,
+            }),
+            _ => None,
+        };
+        // A new order reusing the id of a resting order must not show up in its lifecycle
+        if let Some(action) = received.filter(|_| !self.is_duplicate(&order)) {
+            self.audit(order.user, order.user_order_id, action);
+        }
+        self.statistics.record_message(&order.kind);
         match order.kind {
-            order::Kind::New => self.new_order(order),
+            order::Kind::New => {
+                let statistics = self.statistics.user(order.user);
+                statistics.orders += 1;
+                statistics.ordered_qty = statistics.ordered_qty.saturating_add(order.qty);
+                self.new_order(order)
+            }
             order::Kind::Cancel => self.cancel_order(order),
+            order::Kind::Amend => self.amend_order(order),
             order::Kind::Flush => self.flush(),
+            order::Kind::Transition(state) => self.transition(state),
+            order::Kind::Limits(user, limits) => self.risk_manager.set_limits(user, limits),
+            order::Kind::MassCancel(filter) => self.mass_cancel(&filter),
+            order::Kind::Statistics => {
+                for message in self.statistics_report() {
+                    self.events.send(message);
+                }
+            }
         }
     }
 
@@ -54,28 +438,85 @@ impl OrderBook {
     /// # Args
     /// - `order`: Order to be processed
     fn new_order(&mut self, order: order::Order) {
-        // Reject orders if they cross the book
-        if !self.match_orders && self.crosses_the_book(&order) {
-            self.log_sender
-                .send(format!("R, {}, {}", &order.user, &order.user_order_id))
-                .unwrap();
+        // Reject orders if the session does not accept them
+        if let Some(reason) = self.state.new_order_reject_reason() {
+            self.reject(&order, Some(reason));
+            return;
+        }
+        // Reject orders that expired before they reached the book
+        if order.expiry().is_some_and(|expiry| expiry < self.clock) {
+            self.reject(&order, Some("EXPIRED"));
+            return;
+        }
+        // Reject orders without quantity, which could never leave the book by a trade
+        if order.qty == 0 {
+            self.reject(&order, Some("INVALID_QTY"));
+            return;
+        }
+        // Reject orders whose id is already used by a resting order of the same user
+        if self.is_duplicate(&order) {
+            self.reject(&order, Some("DUPLICATE_ORDER_ID"));
+            return;
+        }
+        // Reject orders that violate the rules of their instrument
+        if let Some(reason) = self.check_instrument(&order) {
+            self.reject(&order, Some(reason));
+            return;
+        }
+        // Reject orders with a price outside of the price bands
+        if let Some(reason) = self.check_price_bands(&order) {
+            self.reject(&order, Some(reason));
+            return;
+        }
+        // Reject orders that breach the risk limits of their user
+        if let Some(reason) = self.check_risk(&order) {
+            self.reject(&order, Some(reason));
+            return;
+        }
+        // Reject orders if they cross the book, unless they are collected for an auction
+        if self.state == session::State::Open && !self.match_orders && self.crosses_the_book(&order)
+        {
+            self.reject(&order, None);
             return;
         }
-        // Log acceptance
-        self.log_sender
-            .send(format!("A, {}, {}", &order.user, &order.user_order_id))
-            .unwrap();
         // Match orders if configured
-        if self.match_orders
-            && match order.side {
-                order::Side::Buy => self.trade_buy_order(&order),
-                order::Side::Sell => self.trade_sell_order(&order),
+        let matching_order = if self.state == session::State::Open && self.match_orders {
+            self.find_match(&order)
+        } else {
+            None
+        };
+        if let Some((price, _)) = matching_order {
+            // Interrupt trading if the trade would print outside of the dynamic price band
+            if !self
+                .price_bands
+                .within_dynamic_band(trade_price(&order, price), self.last_trade_price)
+            {
+                self.transition(self.price_bands.volatility_state);
+                if self.state != session::State::Open {
+                    return self.new_order(order);
+                }
             }
-        {
+        }
+        // Log acceptance
+        self.events.send(event::Message::Ack {
+            user: order.user,
+            order_id: order.user_order_id,
+        });
+        self.audit(order.user, order.user_order_id, audit::Action::Accepted);
+        if let Some((price, pos)) = matching_order {
+            self.trade_order(&order, price, pos);
             return;
         }
         // If no matching was done, write into book
-        let inserter = |book: &mut BTreeMap<i32, Vec<order::Order>>, order: order::Order| {
+        if let Some(expiry) = order.expiry() {
+            self.expiries.push(Reverse(expiry));
+        }
+        self.resting.insert(
+            (order.user, order.user_order_id),
+            (order.side.clone(), order.price),
+        );
+        self.touch(&order);
+        let inserter = |book: &mut BTreeMap<Price, Vec<order::Order>>, order: order::Order| {
             let bucket = book.get_mut(&order.price);
             match bucket {
                 Some(v) => v.push(order),
@@ -96,100 +537,223 @@ impl OrderBook {
         }
     }
 
-    // The following two functions show a high amount of duplication. It could make sense to
-    // refactor both into just one generalized function, either by introducing a conditional or
-    // accept more arguments that determine the behavior from outside.
+    /// Send a reject message for an order to the output thread
+    ///
+    /// # Args
+    /// - `order`: Rejected order
+    /// - `reason`: Reason code of the reject, `None` for orders that would cross the book
+    fn reject(&mut self, order: &order::Order, reason: Option<&str>) {
+        let reason = reason.map(str::to_string);
+        self.events.send(event::Message::Reject {
+            user: order.user,
+            order_id: order.user_order_id,
+            reason: reason.clone(),
+        });
+        self.statistics.user(order.user).rejects += 1;
+        // The status and lifecycle belong to the resting order with the same id
+        if self.is_duplicate(order) {
+            return;
+        }
+        self.audit(
+            order.user,
+            order.user_order_id,
+            audit::Action::Rejected {
+                reason: reason.clone(),
+            },
+        );
+        // Rejected cancellations and amendments do not change the status of the order
+        if let order::Kind::New = order.kind {
+            self.close_order(order, status::Status::Rejected { reason });
+        }
+    }
 
-    /// Try to trade buy order
+    /// Checks if a cancellation or amendment of a network session refers to a resting order that
+    /// the session did not enter. Such orders are treated as unknown to the session.
     ///
     /// # Args
-    /// - `buy_order`: Buy order offered to trade
+    /// - `order`: Cancellation or amendment to be checked
     ///
     /// # Return
-    /// - True if trade was performed, false otherwise
-    fn trade_buy_order(&mut self, buy_order: &order::Order) -> bool {
-        let mut order_traded = false;
-        // TODO how to avoid key_to_be_removed?
-        let mut key_to_be_removed: Option<i32> = None;
-        if let Some(bucket) = self.ask_book.iter_mut().next() {
-            let sell_order_pos = bucket
-                .1
-                .iter()
-                // Only allowing full trades (quantity must match)
-                .position(|x| x.price <= buy_order.price && x.qty == buy_order.qty);
-            if let Some(pos) = sell_order_pos {
-                order_traded = true;
-                let sell_order = &bucket.1[pos];
-                // Log trade
-                self.log_sender
-                    .send(format!(
-                        "T, {}, {}, {}, {}, {}, {}",
-                        buy_order.user,
-                        buy_order.user_order_id,
-                        sell_order.user,
-                        sell_order.user_order_id,
-                        sell_order.price,
-                        sell_order.qty
-                    ))
-                    .unwrap();
-                bucket.1.remove(pos);
-                // If the last order in the bucket was deleted, remove the whole bucket
-                if bucket.1.is_empty() {
-                    key_to_be_removed = Some(*bucket.0);
-                }
+    /// - `true` if the resting order belongs to another session or an input file
+    fn is_foreign(&self, order: &order::Order) -> bool {
+        if order.session.is_none() {
+            return false;
+        }
+        match self.find_resting_order(order) {
+            Some((price, pos, side)) => {
+                let book = match side {
+                    order::Side::Buy => &self.bid_book,
+                    order::Side::Sell => &self.ask_book,
+                };
+                book[&price][pos].session != order.session
             }
+            None => false,
         }
-        if let Some(key) = key_to_be_removed {
-            self.ask_book.remove(&key);
+    }
+
+    /// Checks if a new order uses the id of a resting order of the same user
+    ///
+    /// # Args
+    /// - `order`: Order to be checked
+    ///
+    /// # Return
+    /// - `true` if the order is new and its id is in use, `false` otherwise
+    fn is_duplicate(&self, order: &order::Order) -> bool {
+        matches!(order.kind, order::Kind::New)
+            && self
+                .resting
+                .contains_key(&(order.user, order.user_order_id))
+    }
+
+    /// Record the final status of an order that left the book or was rejected, if configured
+    ///
+    /// # Args
+    /// - `order`: Order carrying the user and order id
+    /// - `status`: Final status of the order
+    fn close_order(&mut self, order: &order::Order, status: status::Status) {
+        if let Some(closed_orders) = &mut self.closed_orders {
+            closed_orders.insert((order.user, order.user_order_id), status);
+        }
+    }
+
+    /// Record a step in the lifecycle of an order in the audit trail, if configured
+    ///
+    /// # Args
+    /// - `user`: User of the order
+    /// - `user_order_id`: Order id assigned by the user
+    /// - `action`: The step
+    fn audit(&mut self, user: u64, user_order_id: u64, action: audit::Action) {
+        let timestamp = self.events.now();
+        if let Some(audit_trail) = &mut self.audit_trail {
+            audit_trail.record(user, user_order_id, timestamp, action);
+        }
+    }
+
+    /// Checks the price of an order against the price bands. The static band is centered around
+    /// the price of the last uncross of the symbol, if there was one.
+    ///
+    /// # Args
+    /// - `order`: Order to be checked
+    ///
+    /// # Return
+    /// - The reason code for rejecting the order or `None` if the price is within the bands
+    fn check_price_bands(&self, order: &order::Order) -> Option<&'static str> {
+        let auction_price = self.auction_prices.get(&order.symbol).copied();
+        self.price_bands
+            .check(order.price, auction_price, self.last_trade_price)
+    }
+
+    /// Checks a new order against the reference data of its instrument
+    ///
+    /// # Args
+    /// - `order`: Order to be checked
+    ///
+    /// # Return
+    /// - The reason code for rejecting the order or `None` if the order is valid
+    fn check_instrument(&self, order: &order::Order) -> Option<&'static str> {
+        if self.instruments.is_empty() {
+            return None;
+        }
+        match self.instruments.get(&order.symbol) {
+            Some(instrument) => instrument.check(order),
+            None => Some("UNKNOWN_SYMBOL"),
         }
-        self.update_lowest_ask();
-        order_traded
     }
 
-    /// Try to trade sell order
+    /// Checks a new or amended order against the risk limits of its user. The resting order an
+    /// amendment replaces does not add to the exposure.
     ///
     /// # Args
-    /// - `sell_order`: Buy order offered to trade
+    /// - `order`: Order to be checked
     ///
     /// # Return
-    /// - True if trade was performed, false otherwise
-    fn trade_sell_order(&mut self, sell_order: &order::Order) -> bool {
-        let mut order_traded = false;
-        // TODO how to avoid key_to_be_removed?
-        let mut key_to_be_removed: Option<i32> = None;
-        if let Some(bucket) = self.bid_book.iter_mut().next_back() {
-            let sell_order_pos = bucket
-                .1
-                .iter()
-                // Only allowing full trades (quantity must match)
-                .position(|x| x.price >= sell_order.price && x.qty == sell_order.qty);
-            if let Some(pos) = sell_order_pos {
-                order_traded = true;
-                let buy_order = &bucket.1[pos];
-                // Log trade
-                self.log_sender
-                    .send(format!(
-                        "T, {}, {}, {}, {}, {}, {}",
-                        buy_order.user,
-                        buy_order.user_order_id,
-                        sell_order.user,
-                        sell_order.user_order_id,
-                        sell_order.price,
-                        sell_order.qty
-                    ))
-                    .unwrap();
-                bucket.1.remove(pos);
-                // If the last order in the bucket was deleted, remove the whole bucket
-                if bucket.1.is_empty() {
-                    key_to_be_removed = Some(*bucket.0);
+    /// - The reason code for rejecting the order or `None` if the order is within the limits
+    fn check_risk(&self, order: &order::Order) -> Option<&'static str> {
+        let mut exposure = risk::Exposure {
+            net_qty: self.positions.net_qty(order.user, &order.symbol),
+            ..Default::default()
+        };
+        if self.risk_manager.needs_exposure(order.user) {
+            let orders = self.bid_book.values().chain(self.ask_book.values());
+            let replaced = |o: &order::Order| o.user_order_id == order.user_order_id;
+            for o in orders
+                .flatten()
+                .filter(|o| o.user == order.user && !replaced(o))
+            {
+                exposure.open_orders += 1;
+                // Positions are limited per symbol, only orders in the same symbol add to them
+                if o.symbol != order.symbol {
+                    continue;
                 }
+                let open_qty = match o.side {
+                    order::Side::Buy => &mut exposure.open_buy_qty,
+                    order::Side::Sell => &mut exposure.open_sell_qty,
+                };
+                *open_qty = open_qty.saturating_add(o.qty);
             }
         }
-        if let Some(key) = key_to_be_removed {
-            self.ask_book.remove(&key);
+        self.risk_manager.check(order, &exposure)
+    }
+
+    /// Find a resting order on the opposite side of the book to trade the given order against
+    ///
+    /// # Args
+    /// - `order`: Order offered to trade
+    ///
+    /// # Return
+    /// - Price and position in the bucket of the matching order, `None` if there is no match
+    fn find_match(&self, order: &order::Order) -> Option<(Price, usize)> {
+        if !self.crosses_the_book(order) {
+            return None;
+        }
+        let price = self.best_price(&order.side.opposite(), &order.symbol)?;
+        let book = match order.side {
+            order::Side::Buy => &self.ask_book,
+            order::Side::Sell => &self.bid_book,
+        };
+        book[&price]
+            .iter()
+            // Only allowing full trades (quantity must match)
+            .position(|x| x.symbol == order.symbol && x.qty == order.qty)
+            .map(|pos| (price, pos))
+    }
+
+    /// Trade an order against a resting order on the opposite side of the book
+    ///
+    /// # Args
+    /// - `order`: Order offered to trade
+    /// - `price`: Price of the bucket containing the matching order
+    /// - `pos`: Position of the matching order in its bucket
+    fn trade_order(&mut self, order: &order::Order, price: Price, pos: usize) {
+        let book = match order.side {
+            order::Side::Buy => &mut self.ask_book,
+            order::Side::Sell => &mut self.bid_book,
+        };
+        let bucket = book.get_mut(&price).unwrap();
+        let resting_order = bucket.remove(pos);
+        // If the last order in the bucket was deleted, remove the whole bucket
+        if bucket.is_empty() {
+            book.remove(&price);
         }
+        self.resting
+            .remove(&(resting_order.user, resting_order.user_order_id));
+        self.touch(&resting_order);
+        // Only full matches trade during continuous trading
+        self.close_order(order, status::Status::Filled);
+        self.close_order(&resting_order, status::Status::Filled);
+        let (buy_order, sell_order) = match order.side {
+            order::Side::Buy => (order, &resting_order),
+            order::Side::Sell => (&resting_order, order),
+        };
+        self.record_trade(
+            buy_order,
+            sell_order,
+            sell_order.price,
+            sell_order.qty,
+            Some(&order.side),
+        );
         self.update_lowest_ask();
-        order_traded
+        self.update_highest_bid();
     }
 
     /// Updates the lowest_ask member and sends a message to the output thread if a change occurred
@@ -198,17 +762,18 @@ impl OrderBook {
         let lowest_bucket = self.ask_book.iter().next();
         match lowest_bucket {
             Some(bucket) => {
-                let price: i32 = *bucket.0;
+                let price: Price = *bucket.0;
                 // Accumulate volume over all orders in bucket
-                let volume: i32 = bucket.1.iter().map(|o| o.qty).sum();
+                let volume: u64 = total_qty(bucket.1);
                 // Check for top of book change
                 if self.lowest_ask.is_none()
                     || self.lowest_ask.unwrap().0 != price
                     || self.lowest_ask.unwrap().1 != volume
                 {
-                    self.log_sender
-                        .send(format!("B, S, {}, {}", price, volume))
-                        .unwrap();
+                    self.events.send(event::Message::TopOfBook {
+                        side: order::Side::Sell,
+                        level: Some((price, volume)),
+                    });
                     self.lowest_ask = Some((price, volume));
                 }
             }
@@ -216,11 +781,15 @@ impl OrderBook {
             None => {
                 // Check if top of book was changed due to a matched order
                 if self.lowest_ask.is_some() {
-                    self.log_sender.send("B, S, -, -".to_string()).unwrap();
+                    self.events.send(event::Message::TopOfBook {
+                        side: order::Side::Sell,
+                        level: None,
+                    });
                     self.lowest_ask = None;
                 }
             }
         }
+        self.observe_top_of_book();
     }
 
     /// Updates the highest_bid member and sends a message to the output thread if a change occurred
@@ -229,17 +798,18 @@ impl OrderBook {
         let highest_bucket = self.bid_book.iter().next_back();
         match highest_bucket {
             Some(bucket) => {
-                let price: i32 = *bucket.0;
+                let price: Price = *bucket.0;
                 // Accumulate volume over all orders in bucket
-                let volume: i32 = bucket.1.iter().map(|o| o.qty).sum();
+                let volume: u64 = total_qty(bucket.1);
                 // Check for top of book change
                 if self.highest_bid.is_none()
                     || self.highest_bid.unwrap().0 != price
                     || self.highest_bid.unwrap().1 != volume
                 {
-                    self.log_sender
-                        .send(format!("B, B, {}, {}", price, volume))
-                        .unwrap();
+                    self.events.send(event::Message::TopOfBook {
+                        side: order::Side::Buy,
+                        level: Some((price, volume)),
+                    });
                     self.highest_bid = Some((price, volume));
                 }
             }
@@ -247,14 +817,54 @@ impl OrderBook {
             None => {
                 // Check if top of book was changed due to a matched order
                 if self.highest_bid.is_some() {
-                    self.log_sender.send("B, B, -, -".to_string()).unwrap();
+                    self.events.send(event::Message::TopOfBook {
+                        side: order::Side::Buy,
+                        level: None,
+                    });
                     self.highest_bid = None;
                 }
             }
         }
+        self.observe_top_of_book();
+    }
+
+    /// Record the current top of book in the statistics
+    fn observe_top_of_book(&mut self) {
+        self.statistics
+            .observe_top_of_book(self.events.now(), self.highest_bid, self.lowest_ask);
     }
 
-    /// Checks if an order would cross the book
+    /// Updates the mid price that positions in a symbol are valued at, if there are orders of
+    /// the symbol on both sides
+    ///
+    /// # Args
+    /// - `symbol`: Symbol to update the mid price of
+    fn update_mark(&mut self, symbol: &str) {
+        let bid = self.best_price(&order::Side::Buy, symbol);
+        let ask = self.best_price(&order::Side::Sell, symbol);
+        if let (Some(ask), Some(bid)) = (ask, bid) {
+            let mid = (i128::from(ask.units()) + i128::from(bid.units())) / 2;
+            self.positions
+                .update_mark(symbol, Price::from_units(mid as i64));
+        }
+    }
+
+    /// Get the best price of the orders of a symbol on one side of the book. Takes O(n) time in
+    /// the number of orders of other symbols at better prices.
+    ///
+    /// # Args
+    /// - `side`: Side of the book
+    /// - `symbol`: Symbol of the orders
+    ///
+    /// # Return
+    /// - The highest bid or lowest ask of the symbol, `None` if it has no orders on that side
+    fn best_price(&self, side: &order::Side, symbol: &str) -> Option<Price> {
+        self.orders(side)
+            .find(|o| o.symbol == symbol)
+            .map(|o| o.price)
+    }
+
+    /// Checks if an order would cross the orders of its symbol on the other side of the book
     ///
     /// # Args
     /// - `order`: Order to be checked
@@ -262,15 +872,12 @@ impl OrderBook {
     /// # Return
     /// - `true` if order would cross the book, `false` otherwise
     fn crosses_the_book(&self, order: &order::Order) -> bool {
-        match order.side {
-            order::Side::Buy => match self.get_lowest_ask() {
-                Some(la) => order.price >= la,
-                None => false,
-            },
-            order::Side::Sell => match self.get_highest_bid() {
-                Some(hb) => order.price <= hb,
-                None => false,
+        match self.best_price(&order.side.opposite(), &order.symbol) {
+            Some(price) => match order.side {
+                order::Side::Buy => order.price >= price,
+                order::Side::Sell => order.price <= price,
             },
+            None => false,
         }
     }
 
@@ -279,12 +886,33 @@ impl OrderBook {
     /// # Args
     /// - `order`: Order to be processed. Is assumed to be a cancel order.
     fn cancel_order(&mut self, order: order::Order) {
-        self.log_sender
-            .send(format!("A, {}, {}", order.user, order.user_order_id))
-            .unwrap();
+        // Reject cancellations if the session does not accept them
+        if let Some(reason) = self.state.cancel_reject_reason() {
+            self.reject(&order, Some(reason));
+            return;
+        }
+        if self.is_foreign(&order) {
+            self.reject(&order, Some("UNKNOWN_ORDER"));
+            return;
+        }
+        self.events.send(event::Message::Ack {
+            user: order.user,
+            order_id: order.user_order_id,
+        });
+        self.audit(order.user, order.user_order_id, audit::Action::Accepted);
+        self.statistics.user(order.user).cancels += 1;
+        self.touch_resting(&order);
+        if self
+            .resting
+            .remove(&(order.user, order.user_order_id))
+            .is_some()
+        {
+            self.close_order(&order, status::Status::Cancelled);
+            self.audit(order.user, order.user_order_id, audit::Action::Cancelled);
+        }
         // Use closure to avoid code duplication below
-        let book_remover = |book: &mut BTreeMap<i32, Vec<order::Order>>, order: &order::Order| {
-            let mut key_to_be_removed: Option<i32> = None;
+        let book_remover = |book: &mut BTreeMap<Price, Vec<order::Order>>, order: &order::Order| {
+            let mut key_to_be_removed: Option<Price> = None;
             for (key, value) in book.iter_mut() {
                 value.retain(|o| o.user != order.user || o.user_order_id != order.user_order_id);
                 if value.is_empty() {
@@ -301,22 +929,632 @@ impl OrderBook {
         self.update_highest_bid();
     }
 
+    /// Process an amendment of the price and quantity of a resting order. The order keeps its time
+    /// priority if only its quantity is reduced, otherwise it moves to the end of its new price
+    /// level. Amendments are checked against the instrument and the price bands, and rejected if
+    /// they would cross the book during continuous trading.
+    ///
+    /// # Args
+    /// - `amendment`: Order carrying the user, order id and the new price and quantity
+    fn amend_order(&mut self, amendment: order::Order) {
+        if let Some(reason) = self.state.new_order_reject_reason() {
+            self.reject(&amendment, Some(reason));
+            return;
+        }
+        let (price, pos, side) = match self.find_resting_order(&amendment) {
+            Some(location) if !self.is_foreign(&amendment) => location,
+            _ => {
+                self.reject(&amendment, Some("UNKNOWN_ORDER"));
+                return;
+            }
+        };
+        let book = match side {
+            order::Side::Buy => &self.bid_book,
+            order::Side::Sell => &self.ask_book,
+        };
+        let resting_order = &book[&price][pos];
+        let mut amended = resting_order.clone();
+        amended.price = amendment.price;
+        amended.qty = amendment.qty;
+        let keeps_priority = amended.price == price && amended.qty <= resting_order.qty;
+        let reason = if amended.qty == 0 {
+            Some("INVALID_QTY")
+        } else {
+            self.check_instrument(&amended)
+                .or_else(|| self.check_price_bands(&amended))
+                .or_else(|| self.check_risk(&amended))
+        };
+        if let Some(reason) = reason {
+            self.reject(&amendment, Some(reason));
+            return;
+        }
+        if self.state == session::State::Open
+            && !self.match_orders
+            && self.crosses_the_book(&amended)
+        {
+            self.reject(&amendment, Some("CROSSING"));
+            return;
+        }
+        // Match amendments that cross the book like new orders if configured
+        let matching_order = if self.state == session::State::Open && self.match_orders {
+            self.find_match(&amended)
+        } else {
+            None
+        };
+        if let Some((match_price, _)) = matching_order {
+            // Interrupt trading if the trade would print outside of the dynamic price band
+            if !self
+                .price_bands
+                .within_dynamic_band(trade_price(&amended, match_price), self.last_trade_price)
+            {
+                self.transition(self.price_bands.volatility_state);
+                if self.state != session::State::Open {
+                    return self.amend_order(amendment);
+                }
+            }
+        }
+        self.events.send(event::Message::Ack {
+            user: amendment.user,
+            order_id: amendment.user_order_id,
+        });
+        self.audit(
+            amendment.user,
+            amendment.user_order_id,
+            audit::Action::Accepted,
+        );
+        self.touch_resting(&amendment);
+        if matching_order.is_none() {
+            self.touch(&amended);
+        }
+        let book = match side {
+            order::Side::Buy => &mut self.bid_book,
+            order::Side::Sell => &mut self.ask_book,
+        };
+        let bucket = book.get_mut(&price).unwrap();
+        if let Some((match_price, match_pos)) = matching_order {
+            bucket.remove(pos);
+            if bucket.is_empty() {
+                book.remove(&price);
+            }
+            self.resting.remove(&(amended.user, amended.user_order_id));
+            self.trade_order(&amended, match_price, match_pos);
+            return;
+        }
+        if keeps_priority {
+            bucket[pos].qty = amended.qty;
+        } else {
+            bucket.remove(pos);
+            if bucket.is_empty() {
+                book.remove(&price);
+            }
+            self.resting
+                .insert((amended.user, amended.user_order_id), (side, amended.price));
+            book.entry(amended.price).or_default().push(amended);
+        }
+        self.update_lowest_ask();
+        self.update_highest_bid();
+    }
+
+    /// Find a resting order by the user and order id of another order
+    ///
+    /// # Args
+    /// - `order`: Order carrying the user and order id to search for
+    ///
+    /// # Return
+    /// - The price level, the position within the level and the side of the resting order, if it
+    ///   is in the book
+    fn find_resting_order(&self, order: &order::Order) -> Option<(Price, usize, order::Side)> {
+        let (side, price) = self.resting.get(&(order.user, order.user_order_id))?;
+        let book = match side {
+            order::Side::Buy => &self.bid_book,
+            order::Side::Sell => &self.ask_book,
+        };
+        let pos = book
+            .get(price)?
+            .iter()
+            .position(|o| o.user == order.user && o.user_order_id == order.user_order_id)?;
+        Some((*price, pos, side.clone()))
+    }
+
+    /// Remember the price level of an order, which is updated in the market data, the marks and
+    /// the indicative prices after the current message
+    ///
+    /// # Args
+    /// - `order`: Order whose price level changed
+    fn touch(&mut self, order: &order::Order) {
+        self.touched.insert(level_key(order));
+    }
+
+    /// Remember the price level of a resting order, see `touch`
+    ///
+    /// # Args
+    /// - `order`: Order carrying the user and order id of the resting order
+    fn touch_resting(&mut self, order: &order::Order) {
+        if let Some((price, pos, side)) = self.find_resting_order(order) {
+            let book = match side {
+                order::Side::Buy => &self.bid_book,
+                order::Side::Sell => &self.ask_book,
+            };
+            self.touched.insert(level_key(&book[&price][pos]));
+        }
+    }
+
+    /// Cancel all resting orders that match a filter. A cancel message is sent to the output
+    /// thread for every removed order, followed by a summary with the number of removed orders.
+    /// Mass cancellations are accepted in every session state.
+    ///
+    /// # Args
+    /// - `filter`: Filter selecting the orders to be cancelled
+    fn mass_cancel(&mut self, filter: &order::CancelFilter) {
+        let events = &mut self.events;
+        let statistics = &mut self.statistics;
+        let closed_orders = &mut self.closed_orders;
+        let resting = &mut self.resting;
+        let touched = &mut self.touched;
+        let audit_trail = &mut self.audit_trail;
+        let now = events.now();
+        let mut count: u64 = 0;
+        let mut remover = |bucket: &mut Vec<order::Order>| {
+            bucket.retain(|o| {
+                if !filter.matches(o) {
+                    return true;
+                }
+                events.send(event::Message::Cancel {
+                    user: o.user,
+                    order_id: o.user_order_id,
+                });
+                statistics.user(o.user).cancels += 1;
+                resting.remove(&(o.user, o.user_order_id));
+                touched.insert(level_key(o));
+                if let Some(closed_orders) = closed_orders.as_mut() {
+                    closed_orders.insert((o.user, o.user_order_id), status::Status::Cancelled);
+                }
+                if let Some(audit_trail) = audit_trail.as_mut() {
+                    let action = audit::Action::MassCancelled;
+                    audit_trail.record(o.user, o.user_order_id, now, action);
+                }
+                count += 1;
+                false
+            })
+        };
+        // Cancel starting from the top of each side
+        self.bid_book.values_mut().rev().for_each(&mut remover);
+        self.ask_book.values_mut().for_each(&mut remover);
+        self.bid_book.retain(|_, orders| !orders.is_empty());
+        self.ask_book.retain(|_, orders| !orders.is_empty());
+        self.events.send(event::Message::MassCancel { count });
+        // Recompute the top of book only once for all removed orders
+        self.update_lowest_ask();
+        self.update_highest_bid();
+    }
+
     /// Flush the order book
     fn flush(&mut self) {
-        self.log_sender.send("".to_string()).unwrap();
+        self.events.send(event::Message::Flush);
+        let orders = self.bid_book.values().chain(self.ask_book.values());
+        self.touched.extend(orders.flatten().map(level_key));
+        if let Some(audit_trail) = &mut self.audit_trail {
+            let now = self.events.now();
+            for o in self
+                .bid_book
+                .values()
+                .chain(self.ask_book.values())
+                .flatten()
+            {
+                audit_trail.record(o.user, o.user_order_id, now, audit::Action::Flushed);
+            }
+        }
+        if let Some(closed_orders) = &mut self.closed_orders {
+            let orders = self.bid_book.values().chain(self.ask_book.values());
+            for o in orders.flatten() {
+                closed_orders.insert((o.user, o.user_order_id), status::Status::Flushed);
+            }
+        }
         self.ask_book.clear();
         self.bid_book.clear();
+        self.resting.clear();
         self.highest_bid = None;
         self.lowest_ask = None;
+        self.observe_top_of_book();
+        self.state = session::State::Open;
+        self.indicative.clear();
+        self.last_trade_price = None;
+        self.auction_prices.clear();
+        // The clock keeps running, so timestamps stay monotonic across flushes
+        self.expiries.clear();
+    }
+
+    /// Move the trading session into another state and send a message to the output thread.
+    /// Transitions that are not allowed from the current state are ignored.
+    ///
+    /// # Args
+    /// - `next`: State to move into
+    fn transition(&mut self, next: session::State) {
+        if !self.state.can_transition_to(next) {
+            return;
+        }
+        let previous = self.state;
+        self.state = next;
+        self.events.send(event::Message::State { state: next });
+        if previous == session::State::PreOpen {
+            // The indicative prices are only valid during the call phase
+            let mut symbols: Vec<_> = self.indicative.drain().map(|(symbol, _)| symbol).collect();
+            symbols.sort();
+            for symbol in symbols {
+                self.events.send(event::Message::Indicative {
+                    symbol,
+                    level: None,
+                });
+            }
+        }
+        // Orders of a call phase may still cross after a halt or the close that ended it
+        if next == session::State::Open {
+            self.uncross();
+        }
+        match next {
+            session::State::PreOpen => {
+                for symbol in self.symbols() {
+                    self.update_indicative(&symbol);
+                }
+            }
+            session::State::Closed => {
+                self.expire_orders(|o| o.time_in_force == order::TimeInForce::Day)
+            }
+            session::State::Open | session::State::Halted => (),
+        }
+    }
+
+    /// Advance the logical clock to an event timestamp and expire all good-till-date orders whose
+    /// expiry the clock passed. Timestamps before the current time do not move the clock back.
+    ///
+    /// # Args
+    /// - `timestamp`: Event timestamp of the current input record
+    fn advance_clock(&mut self, timestamp: u64) {
+        if timestamp <= self.clock {
+            return;
+        }
+        self.clock = timestamp;
+        self.events.set_logical_time(timestamp);
+        let clock = self.clock;
+        // Only search the book if at least one expiry has passed
+        let mut passed = false;
+        while self.expiries.peek().is_some_and(|Reverse(e)| *e < clock) {
+            self.expiries.pop();
+            passed = true;
+        }
+        if passed {
+            self.expire_orders(|o| o.expiry().is_some_and(|expiry| expiry < clock));
+        }
+    }
+
+    /// Remove all orders matching a predicate from the book and send an expiry message for each of
+    /// them to the output thread. Messages are sent in order of the expiry timestamps, orders
+    /// without expiry timestamp first.
+    ///
+    /// # Args
+    /// - `expired`: Predicate that is `true` for orders that expired
+    fn expire_orders<F: Fn(&order::Order) -> bool>(&mut self, expired: F) {
+        let mut expired_orders = Vec::new();
+        let touched = &mut self.touched;
+        for book in [&mut self.bid_book, &mut self.ask_book] {
+            for orders in book.values_mut() {
+                orders.retain(|o| {
+                    if expired(o) {
+                        touched.insert(level_key(o));
+                        expired_orders.push((o.expiry(), o.user, o.user_order_id));
+                        return false;
+                    }
+                    true
+                });
+            }
+            book.retain(|_, orders| !orders.is_empty());
+        }
+        // Stable sort keeps the book order for orders with the same expiry
+        expired_orders.sort_by_key(|(expiry, _, _)| *expiry);
+        for (_, user, user_order_id) in expired_orders {
+            self.resting.remove(&(user, user_order_id));
+            self.events.send(event::Message::Expiry {
+                user,
+                order_id: user_order_id,
+            });
+            if let Some(closed_orders) = &mut self.closed_orders {
+                closed_orders.insert((user, user_order_id), status::Status::Expired);
+            }
+            self.audit(user, user_order_id, audit::Action::Expired);
+        }
+        self.update_highest_bid();
+        self.update_lowest_ask();
+    }
+
+    /// End a call phase by uncrossing the book. The crossing orders of every symbol are executed
+    /// at the equilibrium price of the symbol, orders may be filled partially.
+    fn uncross(&mut self) {
+        for symbol in self.symbols() {
+            let price = match self.equilibrium(&symbol) {
+                Some((price, _)) => price,
+                None => continue,
+            };
+            // Execute in price-time priority as long as there are crossing orders on both sides
+            loop {
+                let first = |side| self.orders(&side).find(|o| o.symbol == symbol).cloned();
+                let bid = first(order::Side::Buy).filter(|o| o.price >= price);
+                let ask = first(order::Side::Sell).filter(|o| o.price <= price);
+                let (bid, ask) = match (bid, ask) {
+                    (Some(bid), Some(ask)) => (bid, ask),
+                    _ => break,
+                };
+                let qty = bid.qty.min(ask.qty);
+                self.record_trade(&bid, &ask, price, qty, None);
+                self.fill_resting(&bid, qty);
+                self.fill_resting(&ask, qty);
+            }
+            self.auction_prices.insert(symbol, price);
+        }
+        self.update_lowest_ask();
+        self.update_highest_bid();
+    }
+
+    /// Reduce the quantity of a resting order by a fill and remove it from the book if it is
+    /// filled completely
+    ///
+    /// # Args
+    /// - `order`: Order carrying the user and order id of the resting order
+    /// - `qty`: Filled quantity
+    fn fill_resting(&mut self, order: &order::Order, qty: u64) {
+        let (price, pos, side) = match self.find_resting_order(order) {
+            Some(location) => location,
+            None => return,
+        };
+        self.touch_resting(order);
+        let book = match side {
+            order::Side::Buy => &mut self.bid_book,
+            order::Side::Sell => &mut self.ask_book,
+        };
+        let bucket = book.get_mut(&price).unwrap();
+        bucket[pos].qty -= qty;
+        if bucket[pos].qty > 0 {
+            return;
+        }
+        bucket.remove(pos);
+        // If the last order in the bucket was filled, remove the whole bucket
+        if bucket.is_empty() {
+            book.remove(&price);
+        }
+        self.resting.remove(&(order.user, order.user_order_id));
+        self.close_order(order, status::Status::Filled);
+    }
+
+    /// Get the symbols of all resting orders
+    fn symbols(&self) -> BTreeSet<String> {
+        let orders = self.bid_book.values().chain(self.ask_book.values());
+        orders.flatten().map(|o| o.symbol.clone()).collect()
+    }
+
+    /// Determine the equilibrium price of the orders of a symbol, i.e. the price at which the most
+    /// volume can be executed. Ties are broken by the lowest surplus between buy and sell volume,
+    /// then by the distance to the last trade price and finally by the lowest price. Takes O(n)
+    /// time in the number of orders in the book.
+    ///
+    /// # Args
+    /// - `symbol`: Symbol of the orders
+    ///
+    /// # Return
+    /// - Tuple of equilibrium price and executable volume, or `None` if the orders do not cross
+    fn equilibrium(&self, symbol: &str) -> Option<(Price, u64)> {
+        let level = |(price, orders): (&Price, &Vec<order::Order>)| {
+            let qty = total_qty(orders.iter().filter(|o| o.symbol == symbol));
+            (qty > 0).then_some((*price, qty))
+        };
+        let mut bids = self.bid_book.iter().filter_map(level).peekable();
+        let mut asks = self.ask_book.iter().filter_map(level).peekable();
+        // Walk the price levels of both sides upwards once, accumulating the sell volume of the
+        // asks at or below and keeping the buy volume of the bids at or above the price
+        let mut buy = total_qty(
+            self.orders(&order::Side::Buy)
+                .filter(|o| o.symbol == symbol),
+        );
+        let mut sell: u64 = 0;
+        let mut best: Option<(Price, u64, u64)> = None;
+        loop {
+            let price = match (bids.peek(), asks.peek()) {
+                (Some(&(bid, _)), Some(&(ask, _))) => bid.min(ask),
+                (Some(&(bid, _)), None) => bid,
+                (None, Some(&(ask, _))) => ask,
+                (None, None) => break,
+            };
+            if let Some((_, qty)) = asks.next_if(|&(ask, _)| ask == price) {
+                sell = sell.saturating_add(qty);
+            }
+            let (demand, supply) = (buy, sell);
+            if let Some((_, qty)) = bids.next_if(|&(bid, _)| bid == price) {
+                buy = buy.saturating_sub(qty);
+            }
+            let volume = demand.min(supply);
+            if volume == 0 {
+                continue;
+            }
+            let surplus = demand.abs_diff(supply);
+            best = match best {
+                None => Some((price, volume, surplus)),
+                Some((best_price, best_volume, best_surplus)) => {
+                    let candidate = (
+                        Reverse(volume),
+                        surplus,
+                        self.reference_distance(price),
+                        price,
+                    );
+                    let current = (
+                        Reverse(best_volume),
+                        best_surplus,
+                        self.reference_distance(best_price),
+                        best_price,
+                    );
+                    if candidate < current {
+                        Some((price, volume, surplus))
+                    } else {
+                        best
+                    }
+                }
+            };
+        }
+        best.map(|(price, volume, _)| (price, volume))
+    }
+
+    /// Distance of a price to the reference price, which is the price of the last trade
+    fn reference_distance(&self, price: Price) -> u64 {
+        match self.last_trade_price {
+            Some(reference) => price.units().abs_diff(reference.units()),
+            None => 0,
+        }
     }
 
-    /// Get the price of the highest bid or None if not available
-    fn get_highest_bid(&self) -> Option<i32> {
-        self.highest_bid.map(|x| x.0)
+    /// Updates the indicative price and volume of a symbol during a call phase and sends a
+    /// message to the output thread if a change occurred
+    ///
+    /// # Args
+    /// - `symbol`: Symbol to update the indicative price and volume of
+    fn update_indicative(&mut self, symbol: &str) {
+        let indicative = self.equilibrium(symbol);
+        if indicative == self.indicative.get(symbol).copied() {
+            return;
+        }
+        self.events.send(event::Message::Indicative {
+            symbol: symbol.to_string(),
+            level: indicative,
+        });
+        match indicative {
+            Some(level) => self.indicative.insert(symbol.to_string(), level),
+            None => self.indicative.remove(symbol),
+        };
+    }
+
+    /// Assign a trade id, send a trade message to the output thread and to the trade tape and
+    /// update the last trade price and the positions of both users
+    ///
+    /// # Args
+    /// - `buy_order`: Order of the buyer
+    /// - `sell_order`: Order of the seller
+    /// - `price`: Price the trade was executed at
+    /// - `qty`: Traded quantity
+    /// - `aggressor`: Side of the order that initiated the trade, `None` for trades of an uncross
+    fn record_trade(
+        &mut self,
+        buy_order: &order::Order,
+        sell_order: &order::Order,
+        price: Price,
+        qty: u64,
+        aggressor: Option<&order::Side>,
+    ) {
+        let trade_id = self.next_trade_id;
+        self.next_trade_id += 1;
+        if let Some(feed) = &mut self.market_data {
+            feed.trade(
+                &buy_order.symbol,
+                trade_id,
+                self.events.now(),
+                price,
+                qty,
+                aggressor,
+            );
+        }
+        let message = event::Message::Trade {
+            buy: (buy_order.user, buy_order.user_order_id),
+            sell: (sell_order.user, sell_order.user_order_id),
+            price,
+            qty,
+            trade_id,
+            aggressor: aggressor.cloned(),
+        };
+        let aggressor = match aggressor {
+            Some(order::Side::Buy) => "B",
+            Some(order::Side::Sell) => "S",
+            None => "-",
+        };
+        let trade = format!(
+            "{}, {}, {}, {}, {}, {}",
+            buy_order.user,
+            buy_order.user_order_id,
+            sell_order.user,
+            sell_order.user_order_id,
+            price,
+            qty
+        );
+        if let Some(checker) = &mut self.invariants {
+            checker.record_fill(buy_order, qty);
+            checker.record_fill(sell_order, qty);
+        }
+        for (order, counterparty) in [(buy_order, sell_order), (sell_order, buy_order)] {
+            let fill = audit::Action::Fill {
+                trade_id,
+                price,
+                qty,
+                remaining_qty: order.qty - qty,
+                counterparty: (counterparty.user, counterparty.user_order_id),
+            };
+            self.audit(order.user, order.user_order_id, fill);
+        }
+        for user in [buy_order.user, sell_order.user] {
+            let statistics = self.statistics.user(user);
+            statistics.trades += 1;
+            statistics.traded_qty = statistics.traded_qty.saturating_add(qty);
+        }
+        if let Some(bars) = &mut self.bars {
+            bars.record_trade(&buy_order.symbol, self.events.now(), price, qty);
+        }
+        if let Some(trade_tape) = &mut self.trade_tape {
+            writeln!(
+                trade_tape,
+                "{}, {}, {}, {}, {}",
+                trade_id,
+                self.events.now(),
+                buy_order.symbol,
+                trade,
+                aggressor
+            )
+            .expect("Could not write trade tape");
+        }
+        self.events.send(message);
+        self.last_trade_price = Some(price);
+        self.positions.record_trade(
+            &buy_order.symbol,
+            buy_order.user,
+            sell_order.user,
+            price,
+            qty,
+        );
     }
+}
 
-    /// Get the price of the lowest ask or None if not available
-    fn get_lowest_ask(&self) -> Option<i32> {
-        self.lowest_ask.map(|x| x.0)
+/// Determine the price a trade between an order and a resting order would be executed at, which is
+/// the price of the sell order
+///
+/// # Args
+/// - `order`: Order offered to trade
+/// - `resting_price`: Price of the resting order
+///
+/// # Return
+/// - Price of the trade
+fn trade_price(order: &order::Order, resting_price: Price) -> Price {
+    match order.side {
+        order::Side::Buy => resting_price,
+        order::Side::Sell => order.price,
     }
 }
+
+/// Get the key of the price level of an order, see `OrderBook::touched`
+fn level_key(order: &order::Order) -> (String, order::Side, Price) {
+    (order.symbol.clone(), order.side.clone(), order.price)
+}
+
+/// Accumulate the quantity of orders. The sum saturates instead of overflowing.
+///
+/// # Args
+/// - `orders`: Orders to accumulate the quantity of
+///
+/// # Return
+/// - Total quantity of the orders
+fn total_qty<'a>(orders: impl IntoIterator<Item = &'a order::Order>) -> u64 {
+    orders
+        .into_iter()
+        .fold(0, |sum: u64, o| sum.saturating_add(o.qty))
+}
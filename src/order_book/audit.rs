@@ -0,0 +1,210 @@
+//! Defines an audit trail that records the lifecycle of every order, from its receipt over its
+//! acceptance or rejection, fills and amendments to the event that removed it from the book
+
+use super::order::Side;
+use super::price::Price;
+use super::status::Status;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{self, Write};
+
+/// One step in the lifecycle of an order
+#[derive(Clone, Debug, PartialEq)]
+pub enum Action {
+    /// A new order was received
+    NewReceived {
+        symbol: String,
+        side: Side,
+        price: Price,
+        qty: u64,
+    },
+    /// A cancellation of the order was received
+    CancelReceived,
+    /// An amendment of the order was received
+    AmendReceived { price: Price, qty: u64 },
+    /// The last received message was accepted
+    Accepted,
+    /// The last received message was rejected, `None` as reason for orders that would cross the
+    /// book
+    Rejected { reason: Option<String> },
+    /// The order traded against another order
+    Fill {
+        trade_id: u64,
+        price: Price,
+        qty: u64,
+        /// Quantity of the order that is left after the fill
+        remaining_qty: u64,
+        /// User and order id of the other order of the trade
+        counterparty: (u64, u64),
+    },
+    /// The order was removed from the book by a cancellation
+    Cancelled,
+    /// The order was removed from the book by a mass cancellation
+    MassCancelled,
+    /// The order was removed from the book because it expired
+    Expired,
+    /// The order was removed from the book by a flush
+    Flushed,
+}
+
+impl fmt::Display for Action {
+    /// Format the action as in the audit report
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Action::NewReceived {
+                symbol,
+                side,
+                price,
+                qty,
+            } => {
+                let side = match side {
+                    Side::Buy => "B",
+                    Side::Sell => "S",
+                };
+                write!(f, "NEW, {}, {}, {}, {}", symbol, side, price, qty)
+            }
+            Action::CancelReceived => write!(f, "CANCEL"),
+            Action::AmendReceived { price, qty } => write!(f, "AMEND, {}, {}", price, qty),
+            Action::Accepted => write!(f, "ACCEPTED"),
+            Action::Rejected { reason } => {
+                write!(f, "REJECTED, {}", reason.as_deref().unwrap_or("-"))
+            }
+            Action::Fill {
+                trade_id,
+                price,
+                qty,
+                remaining_qty,
+                counterparty: (user, user_order_id),
+            } => write!(
+                f,
+                "FILL, {}, {}, {}, {}, {}, {}",
+                trade_id, price, qty, remaining_qty, user, user_order_id
+            ),
+            Action::Cancelled => write!(f, "CANCELLED"),
+            Action::MassCancelled => write!(f, "MASS_CANCELLED"),
+            Action::Expired => write!(f, "EXPIRED"),
+            Action::Flushed => write!(f, "FLUSHED"),
+        }
+    }
+}
+
+/// One step in the lifecycle of an order with the time it happened
+#[derive(Clone, Debug, PartialEq)]
+pub struct Entry {
+    /// Timestamp of the step, using the clock of the output events
+    pub timestamp: u64,
+    pub action: Action,
+}
+
+/// Lifecycle of one order
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct History {
+    /// All steps of the order in the order they happened
+    pub entries: Vec<Entry>,
+    /// State the order ended up in after all steps, `None` while no message was accepted or
+    /// rejected yet
+    pub status: Option<Status>,
+}
+
+impl History {
+    /// Append a step and derive the state of the order from it
+    ///
+    /// # Args
+    /// - `timestamp`: Timestamp of the step
+    /// - `action`: The step
+    fn record(&mut self, timestamp: u64, action: Action) {
+        let received = self.entries.last().map(|entry| &entry.action);
+        let status = match (&action, received) {
+            // Accepting or rejecting a cancellation does not change the order, only removing it
+            (Action::Accepted, Some(Action::NewReceived { qty, .. }))
+            | (Action::Accepted, Some(Action::AmendReceived { qty, .. })) => {
+                Some(Status::Resting { qty: *qty })
+            }
+            (Action::Rejected { reason }, Some(Action::NewReceived { .. })) => {
+                Some(Status::Rejected {
+                    reason: reason.clone(),
+                })
+            }
+            (Action::Fill { remaining_qty, .. }, _) => match remaining_qty {
+                0 => Some(Status::Filled),
+                qty => Some(Status::Resting { qty: *qty }),
+            },
+            (Action::Cancelled, _) | (Action::MassCancelled, _) => Some(Status::Cancelled),
+            (Action::Expired, _) => Some(Status::Expired),
+            (Action::Flushed, _) => Some(Status::Flushed),
+            _ => None,
+        };
+        if status.is_some() {
+            self.status = status;
+        }
+        self.entries.push(Entry { timestamp, action });
+    }
+}
+
+/// Collects the lifecycle of all orders by user and order id. Every new order starts a history of
+/// its own, so orders that reuse the id of an earlier order of the same user, e.g. after it was
+/// filled or flushed, do not continue its history.
+#[derive(Default)]
+pub struct Trail {
+    /// Histories of all orders that used an id, the current one last
+    orders: BTreeMap<(u64, u64), Vec<History>>,
+}
+
+impl Trail {
+    /// Record a step in the lifecycle of an order
+    ///
+    /// # Args
+    /// - `user`: User of the order
+    /// - `user_order_id`: Order id assigned by the user
+    /// - `timestamp`: Timestamp of the step
+    /// - `action`: The step
+    pub fn record(&mut self, user: u64, user_order_id: u64, timestamp: u64, action: Action) {
+        let histories = self.orders.entry((user, user_order_id)).or_default();
+        if histories.is_empty() || matches!(action, Action::NewReceived { .. }) {
+            histories.push(History::default());
+        }
+        if let Some(history) = histories.last_mut() {
+            history.record(timestamp, action);
+        }
+    }
+
+    /// Get the lifecycle of the last order that used an id
+    ///
+    /// # Args
+    /// - `user`: User of the order
+    /// - `user_order_id`: Order id assigned by the user
+    ///
+    /// # Return
+    /// - The lifecycle of the order, `None` if no message for the order was received
+    pub fn history(&self, user: u64, user_order_id: u64) -> Option<&History> {
+        self.orders.get(&(user, user_order_id))?.last()
+    }
+
+    /// Write an audit report of all orders, sorted by user and order id and then by the time the
+    /// orders were received, in the CSV format
+    /// `AE, user_id, order_id, timestamp, action, details` for every step, followed by
+    /// `AF, user_id, order_id, state, details` for the state the order ended up in.
+    ///
+    /// # Args
+    /// - `writer`: Destination of the report
+    ///
+    /// # Return
+    /// An `io::Result` indicating if the report could be written
+    pub fn write_report<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        for ((user, user_order_id), histories) in &self.orders {
+            for history in histories {
+                for entry in &history.entries {
+                    writeln!(
+                        writer,
+                        "AE, {}, {}, {}, {}",
+                        user, user_order_id, entry.timestamp, entry.action
+                    )?;
+                }
+                if let Some(status) = &history.status {
+                    writeln!(writer, "AF, {}, {}, {}", user, user_order_id, status)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,250 @@
+//! Defines the aggregation of trades into open, high, low, close and volume bars per symbol
+
+use super::price::Price;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::fmt;
+use std::io::{self, Write};
+use std::str::FromStr;
+
+/// Enumeration to specify when a bar is completed
+#[derive(Clone, Copy, PartialEq)]
+pub enum Interval {
+    /// Bars cover aligned time buckets of the given length in units of the event timestamps
+    Time(u64),
+    /// Bars are completed as soon as their volume reaches the given quantity
+    Volume(u64),
+    /// Bars are completed after the given number of trades
+    Trades(u64),
+}
+
+impl FromStr for Interval {
+    type Err = String;
+
+    /// Interpret a string in the format `TIME:length`, `VOLUME:quantity` or `TRADES:count`
+    fn from_str(interval: &str) -> Result<Interval, String> {
+        let error = || format!("Invalid bar interval {}", interval);
+        let (kind, size) = interval.split_once(':').ok_or_else(error)?;
+        let size = size
+            .parse::<u64>()
+            .ok()
+            .filter(|size| *size > 0)
+            .ok_or_else(error)?;
+        match kind {
+            "TIME" => Ok(Interval::Time(size)),
+            "VOLUME" => Ok(Interval::Volume(size)),
+            "TRADES" => Ok(Interval::Trades(size)),
+            _ => Err(error()),
+        }
+    }
+}
+
+impl fmt::Display for Interval {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Interval::Time(size) => write!(f, "TIME:{}", size),
+            Interval::Volume(size) => write!(f, "VOLUME:{}", size),
+            Interval::Trades(size) => write!(f, "TRADES:{}", size),
+        }
+    }
+}
+
+/// Enumeration to specify the file format bars are written in
+#[derive(Clone, Copy, PartialEq)]
+pub enum Format {
+    Csv,
+    /// One JSON object per line
+    Json,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Format, String> {
+        match name {
+            "CSV" => Ok(Format::Csv),
+            "JSON" => Ok(Format::Json),
+            _ => Err(format!("Unknown format {}", name)),
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Format::Csv => "CSV",
+            Format::Json => "JSON",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Open, high, low, close, volume and volume-weighted average price of the trades of one symbol
+/// within one interval
+#[derive(Clone, Serialize)]
+pub struct Bar {
+    pub symbol: String,
+    /// Start of the time bucket for time intervals, timestamp of the first trade otherwise
+    pub start: u64,
+    /// Timestamp of the last trade
+    pub end: u64,
+    pub open: Price,
+    pub high: Price,
+    pub low: Price,
+    pub close: Price,
+    pub volume: u64,
+    /// Volume-weighted average price, rounded towards zero
+    pub vwap: Price,
+    /// Number of trades
+    pub trades: u64,
+    /// Sum of price times quantity of all trades, used to calculate the VWAP
+    #[serde(skip)]
+    notional: i128,
+}
+
+impl Bar {
+    /// Start a new bar with its first trade
+    fn new(symbol: &str, start: u64, timestamp: u64, price: Price, qty: u64) -> Bar {
+        Bar {
+            symbol: symbol.to_string(),
+            start,
+            end: timestamp,
+            open: price,
+            high: price,
+            low: price,
+            close: price,
+            volume: qty,
+            vwap: price,
+            trades: 1,
+            notional: i128::from(price.units()) * i128::from(qty),
+        }
+    }
+
+    /// Add a trade to the bar
+    fn add(&mut self, timestamp: u64, price: Price, qty: u64) {
+        self.end = timestamp;
+        self.high = self.high.max(price);
+        self.low = self.low.min(price);
+        self.close = price;
+        self.volume = self.volume.saturating_add(qty);
+        self.trades += 1;
+        self.notional += i128::from(price.units()) * i128::from(qty);
+        if self.volume > 0 {
+            let vwap = self.notional / i128::from(self.volume);
+            self.vwap = i64::try_from(vwap).map_or(self.close, Price::from_units);
+        }
+    }
+
+    /// Write the bar in the CSV format
+    /// `symbol, start, end, open, high, low, close, volume, vwap, trades`
+    fn write_csv<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writeln!(
+            writer,
+            "{}, {}, {}, {}, {}, {}, {}, {}, {}, {}",
+            self.symbol,
+            self.start,
+            self.end,
+            self.open,
+            self.high,
+            self.low,
+            self.close,
+            self.volume,
+            self.vwap,
+            self.trades
+        )
+    }
+}
+
+/// Aggregates the trades of all symbols into bars
+pub struct Aggregator {
+    interval: Interval,
+    /// Bars that are still collecting trades by symbol
+    open_bars: BTreeMap<String, Bar>,
+    /// Completed bars in the order they were completed
+    bars: Vec<Bar>,
+}
+
+impl Aggregator {
+    /// Factory function for constructing a new Aggregator
+    ///
+    /// # Args
+    /// - `interval`: Interval that determines when a bar is completed
+    ///
+    /// # Return
+    /// A new `Aggregator` instance without bars
+    pub fn new(interval: Interval) -> Aggregator {
+        Aggregator {
+            interval,
+            open_bars: BTreeMap::new(),
+            bars: Vec::new(),
+        }
+    }
+
+    /// Add a trade to the bar of its symbol
+    ///
+    /// # Args
+    /// - `symbol`: Traded symbol
+    /// - `timestamp`: Time of the trade
+    /// - `price`: Price of the trade
+    /// - `qty`: Traded quantity
+    pub fn record_trade(&mut self, symbol: &str, timestamp: u64, price: Price, qty: u64) {
+        let start = match self.interval {
+            Interval::Time(length) => timestamp - timestamp % length,
+            Interval::Volume(_) | Interval::Trades(_) => timestamp,
+        };
+        // A trade in another time bucket completes the bar of its symbol
+        if let Interval::Time(_) = self.interval {
+            if self.open_bars.get(symbol).is_some_and(|b| b.start != start) {
+                let bar = self.open_bars.remove(symbol).unwrap();
+                self.bars.push(bar);
+            }
+        }
+        let bar = match self.open_bars.get_mut(symbol) {
+            Some(bar) => {
+                bar.add(timestamp, price, qty);
+                bar
+            }
+            None => self
+                .open_bars
+                .entry(symbol.to_string())
+                .or_insert_with(|| Bar::new(symbol, start, timestamp, price, qty)),
+        };
+        let complete = match self.interval {
+            Interval::Time(_) => false,
+            Interval::Volume(size) => bar.volume >= size,
+            Interval::Trades(size) => bar.trades >= size,
+        };
+        if complete {
+            let bar = self.open_bars.remove(symbol).unwrap();
+            self.bars.push(bar);
+        }
+    }
+
+    /// Iterate over all bars, first the completed bars in the order they were completed and then
+    /// the bars that are still open ordered by symbol
+    pub fn iter(&self) -> impl Iterator<Item = &Bar> {
+        self.bars.iter().chain(self.open_bars.values())
+    }
+
+    /// Write all bars in the given format
+    ///
+    /// # Args
+    /// - `writer`: Destination of the bars
+    /// - `format`: Format to write the bars in
+    ///
+    /// # Return
+    /// An `io::Result` indicating if the bars could be written
+    pub fn write<W: Write>(&self, writer: &mut W, format: Format) -> io::Result<()> {
+        for bar in self.iter() {
+            match format {
+                Format::Csv => bar.write_csv(writer)?,
+                Format::Json => {
+                    serde_json::to_writer(&mut *writer, bar)?;
+                    writeln!(writer)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
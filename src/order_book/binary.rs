@@ -0,0 +1,403 @@
+//! Defines a compact binary format with fixed-layout little-endian messages for order entry and
+//! output events
+//!
+//! Every input message starts with a header of 10 bytes:
+//! `message_type: u8, flags: u8, timestamp: u64`, where bit 0 of the flags is set if the message
+//! carries an event timestamp. The header is followed by a body that depends on the message type:
+//! - `N` (50 bytes): `side: u8` (`B` or `S`), `time_in_force: u8` (0 day, 1 good-till-cancel,
+//!   2 good-till-date), `symbol: [u8; 8]` (zero padded), `user: u64`, `order_id: u64`,
+//!   `price: i64`, `qty: u64`, `expiry: u64`
+//! - `C` (16 bytes): `user: u64`, `order_id: u64`
+//! - `A` (32 bytes): `user: u64`, `order_id: u64`, `price: i64`, `qty: u64` for amendments
+//! - `S` (1 byte): `state: u8` (0 pre-open, 1 open, 2 halted, 3 closed) for transitions
+//! - `M` (28 bytes): `side: u8` (`B`, `S` or 0 for both sides), `user: Option<u64>`,
+//!   `min_price: Option<i64>`, `max_price: Option<i64>` for mass cancellations
+//! - `L` (45 bytes): `user: Option<u64>`, `max_order_qty: Option<u64>`,
+//!   `max_notional: Option<i64>`, `max_open_orders: Option<u64>`,
+//!   `max_net_position: Option<u64>` for risk limits
+//! - `F`, `Q` and `H` (0 bytes) for flushes, statistics and heartbeats of network sessions
+//!
+//! Prices are given as multiples of `10^-price::DECIMALS`. Optional values, like the user of the
+//! default limits or a limit that does not apply, take 9 bytes: a presence byte, which is 1 if the
+//! value is given and 0 otherwise, followed by the value, which is zero if it is not given.
+//!
+//! Every output event starts with a header of 17 bytes: `tag: u8, sequence: u64, timestamp: u64`.
+//! The body contains the fields of the event in the order of its `event::Schema`, where numbers
+//! are `Option<u64>`, prices are `Option<i64>` and texts are `len: u16` followed by `len` bytes of
+//! UTF-8. Texts that are not available are written empty. The separator that is emitted on a
+//! flush has the tag `F` and no body.
+
+use super::event::{self, Event, FieldKind, Value};
+use super::order::{CancelFilter, Kind, Order, Side, TimeInForce};
+use super::price::Price;
+use super::risk::Limits;
+use super::session::State;
+use std::convert::{TryFrom, TryInto};
+use std::io::{self, Read};
+
+/// Length of a symbol in an input message
+const SYMBOL_LEN: usize = 8;
+/// Flag that is set if an input message carries an event timestamp
+const HAS_TIMESTAMP: u8 = 1;
+
+/// Message read from a binary input
+pub enum Message {
+    Order(Order),
+    /// Keeps a network session connected without carrying an order
+    Heartbeat,
+}
+
+/// Encode an order as binary input message
+///
+/// # Args
+/// - `order`: Order or command to be encoded
+///
+/// # Return
+/// - The message or an error if the order cannot be represented in the binary format
+pub fn encode_order(order: &Order) -> Result<Vec<u8>, String> {
+    let message_type = match order.kind {
+        Kind::New => b'N',
+        Kind::Cancel => b'C',
+        Kind::Amend => b'A',
+        Kind::Flush => b'F',
+        Kind::Transition(_) => b'S',
+        Kind::Limits(..) => b'L',
+        Kind::MassCancel(_) => b'M',
+        Kind::Statistics => b'Q',
+    };
+    let mut message = vec![message_type];
+    match order.timestamp {
+        Some(timestamp) => {
+            message.push(HAS_TIMESTAMP);
+            message.extend_from_slice(&timestamp.to_le_bytes());
+        }
+        None => message.extend_from_slice(&[0; 9]),
+    }
+    match order.kind {
+        Kind::New => {
+            message.push(match order.side {
+                Side::Buy => b'B',
+                Side::Sell => b'S',
+            });
+            let (time_in_force, expiry) = match order.time_in_force {
+                TimeInForce::Day => (0, 0),
+                TimeInForce::GoodTillCancel => (1, 0),
+                TimeInForce::GoodTillDate(expiry) => (2, expiry),
+            };
+            message.push(time_in_force);
+            message.extend_from_slice(&encode_text::<SYMBOL_LEN>(&order.symbol)?);
+            message.extend_from_slice(&order.user.to_le_bytes());
+            message.extend_from_slice(&order.user_order_id.to_le_bytes());
+            message.extend_from_slice(&order.price.units().to_le_bytes());
+            message.extend_from_slice(&order.qty.to_le_bytes());
+            message.extend_from_slice(&expiry.to_le_bytes());
+        }
+        Kind::Cancel => {
+            message.extend_from_slice(&order.user.to_le_bytes());
+            message.extend_from_slice(&order.user_order_id.to_le_bytes());
+        }
+        Kind::Amend => {
+            message.extend_from_slice(&order.user.to_le_bytes());
+            message.extend_from_slice(&order.user_order_id.to_le_bytes());
+            message.extend_from_slice(&order.price.units().to_le_bytes());
+            message.extend_from_slice(&order.qty.to_le_bytes());
+        }
+        Kind::Transition(state) => message.push(match state {
+            State::PreOpen => 0,
+            State::Open => 1,
+            State::Halted => 2,
+            State::Closed => 3,
+        }),
+        // Only the order book cancels by network session, so the session is not encoded
+        Kind::MassCancel(ref filter) => {
+            message.push(match filter.side {
+                Some(Side::Buy) => b'B',
+                Some(Side::Sell) => b'S',
+                None => 0,
+            });
+            message.extend_from_slice(&encode_u64(filter.user));
+            message.extend_from_slice(&encode_price(filter.min_price));
+            message.extend_from_slice(&encode_price(filter.max_price));
+        }
+        Kind::Limits(user, ref limits) => {
+            message.extend_from_slice(&encode_u64(user));
+            message.extend_from_slice(&encode_u64(limits.max_order_qty));
+            message.extend_from_slice(&encode_price(limits.max_notional));
+            message.extend_from_slice(&encode_u64(limits.max_open_orders));
+            message.extend_from_slice(&encode_u64(limits.max_net_position));
+        }
+        Kind::Flush | Kind::Statistics => (),
+    }
+    Ok(message)
+}
+
+/// Read the next binary input message
+///
+/// # Args
+/// - `reader`: Reader providing binary input messages
+///
+/// # Return
+/// - The message, `None` if the input ended before a message, or an error if the input is
+///   malformed or ends within a message
+pub fn read_message<R: Read>(reader: &mut R) -> io::Result<Option<Message>> {
+    let mut message_type = [0; 1];
+    if reader.read(&mut message_type)? == 0 {
+        return Ok(None);
+    }
+    let mut header = [0; 9];
+    read_exact(reader, &mut header)?;
+    let timestamp = match header[0] & HAS_TIMESTAMP {
+        0 => None,
+        _ => Some(u64::from_le_bytes(header[1..].try_into().unwrap())),
+    };
+    let mut order = match message_type[0] {
+        b'N' => {
+            let mut body = [0; 50];
+            read_exact(reader, &mut body)?;
+            let side = match body[0] {
+                b'B' => Side::Buy,
+                b'S' => Side::Sell,
+                _ => return Err(invalid_data("Invalid side")),
+            };
+            let expiry = read_u64(&body, 42);
+            let time_in_force = match body[1] {
+                0 => TimeInForce::Day,
+                1 => TimeInForce::GoodTillCancel,
+                2 => TimeInForce::GoodTillDate(expiry),
+                _ => return Err(invalid_data("Invalid time in force")),
+            };
+            Order {
+                kind: Kind::New,
+                user: read_u64(&body, 10),
+                symbol: decode_text(&body[2..10]),
+                price: Price::from_units(read_u64(&body, 26) as i64),
+                qty: read_u64(&body, 34),
+                side,
+                user_order_id: read_u64(&body, 18),
+                time_in_force,
+                session: None,
+                timestamp: None,
+            }
+        }
+        b'C' | b'A' => {
+            let mut body = [0; 32];
+            let len = if message_type[0] == b'C' { 16 } else { 32 };
+            read_exact(reader, &mut body[..len])?;
+            let mut order = Order::new_command(if len == 16 { Kind::Cancel } else { Kind::Amend });
+            order.user = read_u64(&body, 0);
+            order.user_order_id = read_u64(&body, 8);
+            order.price = Price::from_units(read_u64(&body, 16) as i64);
+            order.qty = read_u64(&body, 24);
+            order
+        }
+        b'S' => {
+            let mut body = [0; 1];
+            read_exact(reader, &mut body)?;
+            let state = match body[0] {
+                0 => State::PreOpen,
+                1 => State::Open,
+                2 => State::Halted,
+                3 => State::Closed,
+                _ => return Err(invalid_data("Invalid state")),
+            };
+            Order::new_command(Kind::Transition(state))
+        }
+        b'M' => {
+            let mut body = [0; 28];
+            read_exact(reader, &mut body)?;
+            let side = match body[0] {
+                b'B' => Some(Side::Buy),
+                b'S' => Some(Side::Sell),
+                0 => None,
+                _ => return Err(invalid_data("Invalid side")),
+            };
+            Order::new_command(Kind::MassCancel(CancelFilter {
+                user: read_optional_u64(&body, 1)?,
+                side,
+                min_price: read_optional_price(&body, 10)?,
+                max_price: read_optional_price(&body, 19)?,
+                session: None,
+            }))
+        }
+        b'L' => {
+            let mut body = [0; 45];
+            read_exact(reader, &mut body)?;
+            let limits = Limits {
+                max_order_qty: read_optional_u64(&body, 9)?,
+                max_notional: read_optional_price(&body, 18)?,
+                max_open_orders: read_optional_u64(&body, 27)?,
+                max_net_position: read_optional_u64(&body, 36)?,
+            };
+            Order::new_command(Kind::Limits(read_optional_u64(&body, 0)?, limits))
+        }
+        b'F' => Order::new_command(Kind::Flush),
+        b'Q' => Order::new_command(Kind::Statistics),
+        b'H' => return Ok(Some(Message::Heartbeat)),
+        _ => return Err(invalid_data("Invalid message type")),
+    };
+    order.timestamp = timestamp;
+    Ok(Some(Message::Order(order)))
+}
+
+/// Encode an output event in the binary format
+///
+/// # Args
+/// - `event`: Event to be encoded
+///
+/// # Return
+/// - The encoded event or an error if a text of the event is too long to be encoded
+pub fn encode_event(event: &Event) -> Result<Vec<u8>, String> {
+    let schema = event.message.schema();
+    let mut encoded = vec![schema.map_or(b'F', |s| s.tag)];
+    encoded.extend_from_slice(&event.sequence.to_le_bytes());
+    encoded.extend_from_slice(&event.timestamp.to_le_bytes());
+    let fields = schema.map_or(&[][..], |s| s.fields);
+    let mut values = event.message.values().into_iter();
+    for (_, kind) in fields {
+        let value = values.next().unwrap_or(Value::Missing);
+        match (kind, value) {
+            (FieldKind::Number, Value::Number(number)) => {
+                encoded.extend_from_slice(&encode_u64(Some(number)))
+            }
+            (FieldKind::Number, _) => encoded.extend_from_slice(&encode_u64(None)),
+            (FieldKind::Price, Value::Price(price)) => {
+                encoded.extend_from_slice(&encode_price(Some(price)))
+            }
+            (FieldKind::Price, _) => encoded.extend_from_slice(&encode_price(None)),
+            (FieldKind::Text, Value::Text(text)) => {
+                let len = u16::try_from(text.len())
+                    .map_err(|_| format!("Text of {} is too long", event.message))?;
+                encoded.extend_from_slice(&len.to_le_bytes());
+                encoded.extend_from_slice(text.as_bytes());
+            }
+            (FieldKind::Text, _) => encoded.extend_from_slice(&0_u16.to_le_bytes()),
+        }
+    }
+    Ok(encoded)
+}
+
+/// Read the next output event in the binary format
+///
+/// # Args
+/// - `reader`: Reader providing binary output events
+///
+/// # Return
+/// - The event, `None` if the input ended before an event, or an error if the input is malformed
+pub fn read_event<R: Read>(reader: &mut R) -> io::Result<Option<Event>> {
+    let mut tag = [0; 1];
+    if reader.read(&mut tag)? == 0 {
+        return Ok(None);
+    }
+    let mut header = [0; 16];
+    read_exact(reader, &mut header)?;
+    let message = match tag[0] {
+        b'F' => event::Message::Flush,
+        tag => {
+            let schema =
+                event::schema_by_tag(tag).ok_or_else(|| invalid_data("Invalid message type"))?;
+            let mut values = Vec::new();
+            for (_, kind) in schema.fields {
+                let mut number = [0; 9];
+                let value = match kind {
+                    FieldKind::Number => {
+                        read_exact(reader, &mut number)?;
+                        read_optional_u64(&number, 0)?.map_or(Value::Missing, Value::Number)
+                    }
+                    FieldKind::Price => {
+                        read_exact(reader, &mut number)?;
+                        read_optional_price(&number, 0)?.map_or(Value::Missing, Value::Price)
+                    }
+                    FieldKind::Text => {
+                        let mut len = [0; 2];
+                        read_exact(reader, &mut len)?;
+                        let mut text = vec![0; usize::from(u16::from_le_bytes(len))];
+                        read_exact(reader, &mut text)?;
+                        match String::from_utf8(text) {
+                            Ok(text) if text.is_empty() => Value::Missing,
+                            Ok(text) => Value::Text(text),
+                            Err(_) => return Err(invalid_data("Invalid text")),
+                        }
+                    }
+                };
+                values.push(value);
+            }
+            event::Message::from_values(schema, &values)
+                .ok_or_else(|| invalid_data("Invalid event"))?
+        }
+    };
+    Ok(Some(Event {
+        sequence: read_u64(&header, 0),
+        timestamp: read_u64(&header, 8),
+        message,
+    }))
+}
+
+/// Read the remainder of a message, which must not be cut off by the end of the input
+fn read_exact<R: Read>(reader: &mut R, buffer: &mut [u8]) -> io::Result<()> {
+    reader
+        .read_exact(buffer)
+        .map_err(|error| match error.kind() {
+            io::ErrorKind::UnexpectedEof => invalid_data("Message is truncated"),
+            _ => error,
+        })
+}
+
+/// Read a little-endian `u64` at an offset of a buffer
+fn read_u64(buffer: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(buffer[offset..offset + 8].try_into().unwrap())
+}
+
+/// Read an optional little-endian `u64` at an offset of a buffer, which starts with a presence
+/// byte
+fn read_optional_u64(buffer: &[u8], offset: usize) -> io::Result<Option<u64>> {
+    match buffer[offset] {
+        0 => Ok(None),
+        1 => Ok(Some(read_u64(buffer, offset + 1))),
+        _ => Err(invalid_data("Invalid presence byte")),
+    }
+}
+
+/// Read an optional price at an offset of a buffer, which starts with a presence byte
+fn read_optional_price(buffer: &[u8], offset: usize) -> io::Result<Option<Price>> {
+    let units = read_optional_u64(buffer, offset)?;
+    Ok(units.map(|units| Price::from_units(units as i64)))
+}
+
+/// Encode an optional `u64` as presence byte followed by the value, which is zero for `None`
+fn encode_u64(value: Option<u64>) -> [u8; 9] {
+    let mut encoded = [0; 9];
+    if let Some(value) = value {
+        encoded[0] = 1;
+        encoded[1..].copy_from_slice(&value.to_le_bytes());
+    }
+    encoded
+}
+
+/// Encode an optional price like an optional `u64`
+fn encode_price(price: Option<Price>) -> [u8; 9] {
+    encode_u64(price.map(|price| price.units() as u64))
+}
+
+/// Encode a text as zero padded ASCII of a fixed length
+fn encode_text<const LEN: usize>(text: &str) -> Result<[u8; LEN], String> {
+    if text.len() > LEN {
+        return Err(format!("{} is longer than {} bytes", text, LEN));
+    }
+    let mut encoded = [0; LEN];
+    encoded[..text.len()].copy_from_slice(text.as_bytes());
+    Ok(encoded)
+}
+
+/// Decode a zero padded text
+fn decode_text(encoded: &[u8]) -> String {
+    let len = encoded
+        .iter()
+        .position(|b| *b == 0)
+        .unwrap_or(encoded.len());
+    String::from_utf8_lossy(&encoded[..len]).into_owned()
+}
+
+/// Create an error for malformed binary input
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
@@ -0,0 +1,607 @@
+//! Defines the events an order book emits and the publisher that numbers and timestamps them
+
+use super::order::Side;
+use super::price;
+use super::session;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::mpsc::Sender;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// One output event of an order book
+#[derive(Clone, Debug, PartialEq)]
+pub struct Event {
+    /// Sequence number, increasing by one with every event
+    pub sequence: u64,
+    /// Time the event was emitted at, see `Clock`
+    pub timestamp: u64,
+    /// Content of the event
+    pub message: Message,
+}
+
+impl Event {
+    /// Format the event as line of the CSV output
+    ///
+    /// # Args
+    /// - `header`: Prefix the message with the sequence number and timestamp
+    ///
+    /// # Return
+    /// - The CSV line, which is empty for the separator that is emitted on a flush
+    pub fn to_csv(&self, header: bool) -> String {
+        if !header || self.message == Message::Flush {
+            return self.message.to_string();
+        }
+        format!("{}, {}, {}", self.sequence, self.timestamp, self.message)
+    }
+}
+
+/// Content of an output event. The fields of every message type are described by its `Schema`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Message {
+    /// A new order, cancellation or amendment was accepted
+    Ack { user: u64, order_id: u64 },
+    /// A new order, cancellation or amendment was rejected, `None` as reason for orders that
+    /// would cross the book
+    Reject {
+        user: u64,
+        order_id: u64,
+        reason: Option<String>,
+    },
+    /// The price and volume of one side of the top of book changed, `None` if the side is empty
+    TopOfBook {
+        side: Side,
+        level: Option<(price::Price, u64)>,
+    },
+    /// Two orders traded, `None` as aggressor for trades of an uncross
+    Trade {
+        buy: (u64, u64),
+        sell: (u64, u64),
+        price: price::Price,
+        qty: u64,
+        trade_id: u64,
+        aggressor: Option<Side>,
+    },
+    /// The indicative uncross price and volume of a symbol changed, `None` if its orders do not
+    /// cross
+    Indicative {
+        symbol: String,
+        level: Option<(price::Price, u64)>,
+    },
+    /// The session moved into another state
+    State { state: session::State },
+    /// A good-till-date or day order expired
+    Expiry { user: u64, order_id: u64 },
+    /// A resting order was removed by a mass cancellation
+    Cancel { user: u64, order_id: u64 },
+    /// A mass cancellation removed the given number of orders
+    MassCancel { count: u64 },
+    /// Statistics of one user, see `statistics::UserStatistics`
+    UserStatistics {
+        user: u64,
+        orders: u64,
+        cancels: u64,
+        rejects: u64,
+        trades: u64,
+        fill_ratio: Option<f64>,
+        cancel_to_trade_ratio: Option<f64>,
+    },
+    /// Time-weighted statistics of the top of book, `None` if the book was never two-sided
+    BookStatistics {
+        time_weighted_spread: Option<price::Price>,
+        average_bid_depth: Option<f64>,
+        average_ask_depth: Option<f64>,
+    },
+    /// Number of received messages of one type of the CSV input
+    MessageStatistics { message_type: String, count: u64 },
+    /// Separator that is emitted on a flush
+    Flush,
+}
+
+/// Value of one field of an output message
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Number(u64),
+    Price(price::Price),
+    Text(String),
+    /// Value that is not available, written as `-`
+    Missing,
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Number(number) => write!(f, "{}", number),
+            Value::Price(price) => write!(f, "{}", price),
+            Value::Text(text) => write!(f, "{}", text),
+            Value::Missing => write!(f, "-"),
+        }
+    }
+}
+
+impl Message {
+    /// Get the layout of the message
+    ///
+    /// # Return
+    /// - The layout or `None` for the separator that is emitted on a flush
+    pub fn schema(&self) -> Option<&'static Schema> {
+        let message_type = match self {
+            Message::Ack { .. } => "A",
+            Message::Reject { .. } => "R",
+            Message::TopOfBook { .. } => "B",
+            Message::Trade { .. } => "T",
+            Message::Indicative { .. } => "I",
+            Message::State { .. } => "S",
+            Message::Expiry { .. } => "E",
+            Message::Cancel { .. } => "X",
+            Message::MassCancel { .. } => "M",
+            Message::UserStatistics { .. } => "SU",
+            Message::BookStatistics { .. } => "SB",
+            Message::MessageStatistics { .. } => "SM",
+            Message::Flush => return None,
+        };
+        schema(message_type)
+    }
+
+    /// Get the values of the fields of the message in the order of its `Schema`. Optional trailing
+    /// fields, like the reason of a reject, are left out if they are not available.
+    pub fn values(&self) -> Vec<Value> {
+        let level = |level: &Option<(price::Price, u64)>| match level {
+            Some((price, volume)) => vec![Value::Price(*price), Value::Number(*volume)],
+            None => vec![Value::Missing, Value::Missing],
+        };
+        let decimal = |value: &Option<f64>, decimals: usize| match value {
+            Some(value) => Value::Text(format!("{:.*}", decimals, value)),
+            None => Value::Missing,
+        };
+        match self {
+            Message::Ack { user, order_id }
+            | Message::Expiry { user, order_id }
+            | Message::Cancel { user, order_id } => {
+                vec![Value::Number(*user), Value::Number(*order_id)]
+            }
+            Message::Reject {
+                user,
+                order_id,
+                reason,
+            } => {
+                let mut values = vec![Value::Number(*user), Value::Number(*order_id)];
+                values.extend(reason.iter().map(|reason| Value::Text(reason.clone())));
+                values
+            }
+            Message::TopOfBook { side, level: top } => {
+                let mut values = vec![Value::Text(side_code(side).to_string())];
+                values.extend(level(top));
+                values
+            }
+            Message::Trade {
+                buy,
+                sell,
+                price,
+                qty,
+                trade_id,
+                aggressor,
+            } => vec![
+                Value::Number(buy.0),
+                Value::Number(buy.1),
+                Value::Number(sell.0),
+                Value::Number(sell.1),
+                Value::Price(*price),
+                Value::Number(*qty),
+                Value::Number(*trade_id),
+                aggressor.as_ref().map_or(Value::Missing, |side| {
+                    Value::Text(side_code(side).to_string())
+                }),
+            ],
+            Message::Indicative {
+                symbol,
+                level: indicative,
+            } => {
+                let mut values = vec![Value::Text(symbol.clone())];
+                values.extend(level(indicative));
+                values
+            }
+            Message::State { state } => vec![Value::Text(state.to_string())],
+            Message::MassCancel { count } => vec![Value::Number(*count)],
+            Message::UserStatistics {
+                user,
+                orders,
+                cancels,
+                rejects,
+                trades,
+                fill_ratio,
+                cancel_to_trade_ratio,
+            } => vec![
+                Value::Number(*user),
+                Value::Number(*orders),
+                Value::Number(*cancels),
+                Value::Number(*rejects),
+                Value::Number(*trades),
+                decimal(fill_ratio, 4),
+                decimal(cancel_to_trade_ratio, 4),
+            ],
+            Message::BookStatistics {
+                time_weighted_spread,
+                average_bid_depth,
+                average_ask_depth,
+            } => vec![
+                time_weighted_spread.map_or(Value::Missing, Value::Price),
+                decimal(average_bid_depth, 2),
+                decimal(average_ask_depth, 2),
+            ],
+            Message::MessageStatistics {
+                message_type,
+                count,
+            } => vec![Value::Text(message_type.clone()), Value::Number(*count)],
+            Message::Flush => Vec::new(),
+        }
+    }
+
+    /// Restore a message from the values of its fields, e.g. after reading it from the binary
+    /// format
+    ///
+    /// # Args
+    /// - `schema`: Layout of the message
+    /// - `values`: Values of the fields in the order of the layout
+    ///
+    /// # Return
+    /// - The message or `None` if the values do not fit the layout
+    pub fn from_values(schema: &Schema, values: &[Value]) -> Option<Message> {
+        let number = |i: usize| match values.get(i)? {
+            Value::Number(number) => Some(*number),
+            _ => None,
+        };
+        let text = |i: usize| match values.get(i) {
+            Some(Value::Text(text)) => Some(Some(text.as_str())),
+            Some(Value::Missing) | None => Some(None),
+            _ => None,
+        };
+        let level = |i: usize| match (values.get(i)?, values.get(i + 1)?) {
+            (Value::Price(price), Value::Number(volume)) => Some(Some((*price, *volume))),
+            (Value::Missing, Value::Missing) => Some(None),
+            _ => None,
+        };
+        let decimal = |i: usize| match text(i)? {
+            Some(text) => text.parse::<f64>().ok().map(Some),
+            None => Some(None),
+        };
+        let message = match schema.message_type {
+            "A" => Message::Ack {
+                user: number(0)?,
+                order_id: number(1)?,
+            },
+            "R" => Message::Reject {
+                user: number(0)?,
+                order_id: number(1)?,
+                reason: text(2)?.map(str::to_string),
+            },
+            "B" => Message::TopOfBook {
+                side: parse_side(text(0)??)?,
+                level: level(1)?,
+            },
+            "T" => Message::Trade {
+                buy: (number(0)?, number(1)?),
+                sell: (number(2)?, number(3)?),
+                price: match values.get(4)? {
+                    Value::Price(price) => *price,
+                    _ => return None,
+                },
+                qty: number(5)?,
+                trade_id: number(6)?,
+                aggressor: match text(7)? {
+                    Some(side) => Some(parse_side(side)?),
+                    None => None,
+                },
+            },
+            "I" => Message::Indicative {
+                symbol: text(0)??.to_string(),
+                level: level(1)?,
+            },
+            "S" => Message::State {
+                state: text(0)??.parse().ok()?,
+            },
+            "E" => Message::Expiry {
+                user: number(0)?,
+                order_id: number(1)?,
+            },
+            "X" => Message::Cancel {
+                user: number(0)?,
+                order_id: number(1)?,
+            },
+            "M" => Message::MassCancel { count: number(0)? },
+            "SU" => Message::UserStatistics {
+                user: number(0)?,
+                orders: number(1)?,
+                cancels: number(2)?,
+                rejects: number(3)?,
+                trades: number(4)?,
+                fill_ratio: decimal(5)?,
+                cancel_to_trade_ratio: decimal(6)?,
+            },
+            "SB" => Message::BookStatistics {
+                time_weighted_spread: match values.first()? {
+                    Value::Price(price) => Some(*price),
+                    Value::Missing => None,
+                    _ => return None,
+                },
+                average_bid_depth: decimal(1)?,
+                average_ask_depth: decimal(2)?,
+            },
+            "SM" => Message::MessageStatistics {
+                message_type: text(0)??.to_string(),
+                count: number(1)?,
+            },
+            _ => return None,
+        };
+        Some(message)
+    }
+}
+
+impl fmt::Display for Message {
+    /// Format the message in the CSV output format, e.g. `A, 1, 1`. The separator that is emitted
+    /// on a flush is empty. Trades keep their original format without trade id and aggressor,
+    /// which are only part of the JSONL and binary output and the trade tape.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(schema) = self.schema() {
+            write!(f, "{}", schema.message_type)?;
+            let mut values = self.values();
+            if let Message::Trade { .. } = self {
+                values.truncate(CSV_TRADE_FIELDS);
+            }
+            for value in values {
+                write!(f, ", {}", value)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Number of trade fields in the CSV output, which leaves out the trade id and the aggressor
+const CSV_TRADE_FIELDS: usize = 6;
+
+/// Code of a side in the output formats
+fn side_code(side: &Side) -> &'static str {
+    match side {
+        Side::Buy => "B",
+        Side::Sell => "S",
+    }
+}
+
+/// Interpret the code of a side in the output formats
+fn parse_side(code: &str) -> Option<Side> {
+    match code {
+        "B" => Some(Side::Buy),
+        "S" => Some(Side::Sell),
+        _ => None,
+    }
+}
+
+/// Kind of the value of an event field
+#[derive(Clone, Copy, PartialEq)]
+pub enum FieldKind {
+    /// Integer such as a user id or quantity
+    Number,
+    /// Decimal price
+    Price,
+    /// Text such as a reason code or side
+    Text,
+}
+
+use FieldKind::{Number, Price, Text};
+
+/// Layout of one type of output message
+pub struct Schema {
+    /// First field of the CSV message
+    pub message_type: &'static str,
+    /// Descriptive name of the message type
+    pub name: &'static str,
+    /// Identifier of the message type in the binary format
+    pub tag: u8,
+    /// Names and kinds of the remaining fields of the CSV message
+    pub fields: &'static [(&'static str, FieldKind)],
+}
+
+/// Layouts of all output message types, except for the empty separator that is emitted on a flush
+static SCHEMAS: &[Schema] = &[
+    Schema {
+        message_type: "A",
+        name: "ack",
+        tag: b'A',
+        fields: &[("user", Number), ("order_id", Number)],
+    },
+    Schema {
+        message_type: "R",
+        name: "reject",
+        tag: b'R',
+        fields: &[("user", Number), ("order_id", Number), ("reason", Text)],
+    },
+    Schema {
+        message_type: "B",
+        name: "top_of_book",
+        tag: b'B',
+        fields: &[("side", Text), ("price", Price), ("volume", Number)],
+    },
+    Schema {
+        message_type: "T",
+        name: "trade",
+        tag: b'T',
+        fields: &[
+            ("buy_user", Number),
+            ("buy_order_id", Number),
+            ("sell_user", Number),
+            ("sell_order_id", Number),
+            ("price", Price),
+            ("qty", Number),
+            ("trade_id", Number),
+            ("aggressor", Text),
+        ],
+    },
+    Schema {
+        message_type: "I",
+        name: "indicative",
+        tag: b'I',
+        fields: &[("symbol", Text), ("price", Price), ("volume", Number)],
+    },
+    Schema {
+        message_type: "S",
+        name: "state",
+        tag: b'S',
+        fields: &[("state", Text)],
+    },
+    Schema {
+        message_type: "E",
+        name: "expiry",
+        tag: b'E',
+        fields: &[("user", Number), ("order_id", Number)],
+    },
+    Schema {
+        message_type: "X",
+        name: "cancel",
+        tag: b'X',
+        fields: &[("user", Number), ("order_id", Number)],
+    },
+    Schema {
+        message_type: "M",
+        name: "mass_cancel",
+        tag: b'M',
+        fields: &[("count", Number)],
+    },
+    Schema {
+        message_type: "SU",
+        name: "user_statistics",
+        tag: b'u',
+        fields: &[
+            ("user", Number),
+            ("orders", Number),
+            ("cancels", Number),
+            ("rejects", Number),
+            ("trades", Number),
+            ("fill_ratio", Text),
+            ("cancel_to_trade_ratio", Text),
+        ],
+    },
+    Schema {
+        message_type: "SB",
+        name: "book_statistics",
+        tag: b'b',
+        fields: &[
+            ("time_weighted_spread", Price),
+            ("average_bid_depth", Text),
+            ("average_ask_depth", Text),
+        ],
+    },
+    Schema {
+        message_type: "SM",
+        name: "message_statistics",
+        tag: b'm',
+        fields: &[("message_type", Text), ("count", Number)],
+    },
+];
+
+/// Get the layout of an output message type
+///
+/// # Args
+/// - `message_type`: First field of a CSV output message
+///
+/// # Return
+/// - The layout or `None` for unknown message types
+pub fn schema(message_type: &str) -> Option<&'static Schema> {
+    SCHEMAS.iter().find(|s| s.message_type == message_type)
+}
+
+/// Get the layout of an output message type by its identifier in the binary format
+pub fn schema_by_tag(tag: u8) -> Option<&'static Schema> {
+    SCHEMAS.iter().find(|s| s.tag == tag)
+}
+
+/// Enumeration to specify the clock events are timestamped with
+#[derive(Clone, Copy, PartialEq)]
+pub enum Clock {
+    /// Logical clock of the order book, advanced by the event timestamps of the input records
+    Logical,
+    /// Wall clock in milliseconds since the Unix epoch
+    Wall,
+}
+
+impl FromStr for Clock {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Clock, String> {
+        match name {
+            "LOGICAL" => Ok(Clock::Logical),
+            "WALL" => Ok(Clock::Wall),
+            _ => Err(format!("Unknown clock {}", name)),
+        }
+    }
+}
+
+impl fmt::Display for Clock {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Clock::Logical => "LOGICAL",
+            Clock::Wall => "WALL",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Sends events to the output thread and assigns their sequence numbers and timestamps
+pub struct Publisher {
+    sender: Sender<Event>,
+    clock: Clock,
+    /// Sequence number of the next event
+    sequence: u64,
+    /// Current time of the logical clock
+    logical_time: u64,
+}
+
+impl Publisher {
+    /// Factory function for constructing a new Publisher
+    ///
+    /// # Args
+    /// - `sender`: A mpsc sender used to send events to the output thread
+    ///
+    /// # Return
+    /// A new `Publisher` instance using the logical clock
+    pub fn new(sender: Sender<Event>) -> Publisher {
+        Publisher {
+            sender,
+            clock: Clock::Logical,
+            sequence: 1,
+            logical_time: 0,
+        }
+    }
+
+    /// Select the clock events are timestamped with
+    pub fn set_clock(&mut self, clock: Clock) {
+        self.clock = clock;
+    }
+
+    /// Update the time of the logical clock
+    pub fn set_logical_time(&mut self, time: u64) {
+        self.logical_time = time;
+    }
+
+    /// Get the current time of the selected clock
+    pub fn now(&self) -> u64 {
+        match self.clock {
+            Clock::Logical => self.logical_time,
+            Clock::Wall => SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |time| time.as_millis() as u64),
+        }
+    }
+
+    /// Send an event with the next sequence number and the current time
+    ///
+    /// # Args
+    /// - `message`: Content of the event
+    pub fn send(&mut self, message: Message) {
+        let event = Event {
+            sequence: self.sequence,
+            timestamp: self.now(),
+            message,
+        };
+        self.sequence += 1;
+        self.sender.send(event).unwrap();
+    }
+}
@@ -0,0 +1,40 @@
+//! Defines the formats the input and output of an order book can be exchanged in
+
+use std::fmt;
+use std::str::FromStr;
+
+/// Enumeration to specify the format of the input or output
+#[derive(Clone, Copy, PartialEq)]
+pub enum Format {
+    /// Headerless CSV with positional fields
+    Csv,
+    /// One self-describing JSON object per line, see `jsonl`
+    Jsonl,
+    /// Fixed-layout little-endian messages, see `binary`
+    Binary,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    /// Interpret the name of a format, ignoring its case
+    fn from_str(name: &str) -> Result<Format, String> {
+        match name.to_ascii_uppercase().as_str() {
+            "CSV" => Ok(Format::Csv),
+            "JSONL" => Ok(Format::Jsonl),
+            "BINARY" => Ok(Format::Binary),
+            _ => Err(format!("Unknown format {}", name)),
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Format::Csv => "CSV",
+            Format::Jsonl => "JSONL",
+            Format::Binary => "BINARY",
+        };
+        write!(f, "{}", name)
+    }
+}
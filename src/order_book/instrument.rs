@@ -0,0 +1,193 @@
+//! Defines instrument reference data that new orders are validated against
+
+use super::order::Order;
+use super::price::Price;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+use std::str::FromStr;
+
+static CONFIG_ERROR_MSG: &str =
+    "Malformed instrument config! Check your config file and try again.";
+
+/// Reference data of one tradable instrument
+#[derive(Clone)]
+pub struct Instrument {
+    /// Maximum number of decimals of a price. Only restricts new orders, prices are written
+    /// without trailing zeros regardless of the scale.
+    pub price_scale: u32,
+    /// Tick size tiers as tuple of the price a tier starts at and its tick size, sorted by price
+    pub tick_sizes: Vec<(Price, Price)>,
+    /// Quantities must be a multiple of the lot size
+    pub lot_size: u64,
+    /// Minimum quantity of an order
+    pub min_qty: u64,
+    /// Maximum quantity of an order
+    pub max_qty: u64,
+}
+
+impl Instrument {
+    /// Determine the tick size that applies to a price
+    ///
+    /// # Args
+    /// - `price`: Price to determine the tick size for
+    ///
+    /// # Return
+    /// - The tick size of the highest tier starting at or below the price, if there is one
+    pub fn tick_size(&self, price: Price) -> Option<Price> {
+        self.tick_sizes
+            .iter()
+            .rev()
+            .find(|(from_price, _)| *from_price <= price)
+            .map(|(_, tick_size)| *tick_size)
+    }
+
+    /// Checks if a new order complies with the rules of the instrument
+    ///
+    /// # Args
+    /// - `order`: New order to be checked
+    ///
+    /// # Return
+    /// - The reason code for rejecting the order or `None` if the order is valid
+    pub fn check(&self, order: &Order) -> Option<&'static str> {
+        if !order.price.fits_decimals(self.price_scale) {
+            return Some("PRICE_SCALE");
+        }
+        if let Some(tick_size) = self.tick_size(order.price) {
+            if order.price.units() % tick_size.units() != 0 {
+                return Some("TICK_SIZE");
+            }
+        }
+        if order.qty < self.min_qty {
+            return Some("MIN_QTY");
+        }
+        if order.qty > self.max_qty {
+            return Some("MAX_QTY");
+        }
+        if !order.qty.is_multiple_of(self.lot_size) {
+            return Some("LOT_SIZE");
+        }
+        None
+    }
+}
+
+/// Load instrument definitions from a CSV config file. The file contains instruments in the
+/// format `I, symbol, price_scale, lot_size, min_quantity, max_quantity` and tick size tiers in the
+/// format `K, symbol, from_price, tick_size`. Tier prices and tick sizes are given as decimals.
+///
+/// # Args
+/// - `path`: Path of the config file
+///
+/// # Return
+/// A `Result` containing the instruments by symbol or an `csv::Error`, if there is an issue with
+/// reading the provided CSV file.
+pub fn load(path: &Path) -> Result<HashMap<String, Instrument>, csv::Error> {
+    read(File::open(path)?)
+}
+
+/// Read instrument definitions in the format described for `load`
+///
+/// # Args
+/// - `config`: Reader providing the content of a config file
+///
+/// # Return
+/// A `Result` containing the instruments by symbol or an `csv::Error`, if there is an issue with
+/// reading the provided CSV data or an instrument is invalid.
+pub fn read<R: Read>(config: R) -> Result<HashMap<String, Instrument>, csv::Error> {
+    let mut reader = csv::ReaderBuilder::new()
+        .comment(Some(b'#'))
+        .has_headers(false)
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(config);
+    let mut instruments: HashMap<String, Instrument> = HashMap::new();
+    for result in reader.records() {
+        let record = result?;
+        match parse::<String>(&record, 0)?.as_str() {
+            "I" => {
+                instruments.insert(
+                    parse(&record, 1)?,
+                    Instrument {
+                        price_scale: parse(&record, 2)?,
+                        tick_sizes: Vec::new(),
+                        lot_size: parse(&record, 3)?,
+                        min_qty: parse(&record, 4)?,
+                        max_qty: parse(&record, 5)?,
+                    },
+                );
+            }
+            "K" => {
+                let symbol: String = parse(&record, 1)?;
+                let instrument = instruments
+                    .get_mut(&symbol)
+                    .ok_or_else(|| malformed(&record))?;
+                instrument
+                    .tick_sizes
+                    .push((parse(&record, 2)?, parse(&record, 3)?));
+                instrument.tick_sizes.sort_unstable();
+            }
+            _ => return Err(malformed(&record)),
+        }
+    }
+    for (symbol, instrument) in &instruments {
+        validate(symbol, instrument)?;
+    }
+    Ok(instruments)
+}
+
+/// Parse a field of a config record
+///
+/// # Args
+/// - `record`: Record of the config file
+/// - `i`: Index of the field
+///
+/// # Return
+/// The parsed field or an `csv::Error`, if the field is missing or malformed
+fn parse<T: FromStr>(record: &csv::StringRecord, i: usize) -> Result<T, csv::Error> {
+    record
+        .get(i)
+        .and_then(|field| field.parse().ok())
+        .ok_or_else(|| malformed(record))
+}
+
+/// Create the error for a malformed record of the config file
+fn malformed(record: &csv::StringRecord) -> csv::Error {
+    let line = record.position().map_or(0, |position| position.line());
+    csv::Error::from(io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("{} Line: {}", CONFIG_ERROR_MSG, line),
+    ))
+}
+
+/// Checks that an instrument can be used to validate orders, i.e. that its tick sizes and lot size
+/// are positive and its minimum quantity does not exceed its maximum quantity
+///
+/// # Args
+/// - `symbol`: Symbol of the instrument
+/// - `instrument`: Instrument to be checked
+///
+/// # Return
+/// An `csv::Error` describing the first issue with the instrument, if there is one
+fn validate(symbol: &str, instrument: &Instrument) -> Result<(), csv::Error> {
+    let issue = if instrument.lot_size == 0 {
+        Some("lot size must be positive")
+    } else if instrument.min_qty > instrument.max_qty {
+        Some("minimum quantity must not exceed maximum quantity")
+    } else if instrument
+        .tick_sizes
+        .iter()
+        .any(|(_, tick_size)| tick_size.units() <= 0)
+    {
+        Some("tick sizes must be positive")
+    } else {
+        None
+    };
+    match issue {
+        Some(issue) => Err(csv::Error::from(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Invalid instrument {}: {}", symbol, issue),
+        ))),
+        None => Ok(()),
+    }
+}
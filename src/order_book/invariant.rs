@@ -0,0 +1,207 @@
+//! Defines a checker of the invariants of an order book, which runs after every message in tests
+//! and fuzzing
+
+use super::order::{Kind, Order, Side};
+use super::price::Price;
+use super::{session, total_qty, OrderBook};
+use std::collections::{BTreeMap, HashMap};
+
+/// Identifies a resting order by user and order id
+type Key = (u64, u64);
+
+/// Checks the invariants of an order book after every message. Besides the structure of the book,
+/// it verifies that the quantity of every resting order is conserved: quantity only leaves the
+/// book by fills or by the message that removed the order.
+#[derive(Default)]
+pub struct Checker {
+    /// Quantity of every resting order after the last message
+    resting: HashMap<Key, u64>,
+    /// Session state after the last message, `None` before the first message
+    state: Option<session::State>,
+    /// Quantity filled per order while processing the current message
+    fills: HashMap<Key, u64>,
+}
+
+impl Checker {
+    /// Record a fill of an order while processing the current message
+    ///
+    /// # Args
+    /// - `order`: Resting or incoming order that was filled
+    /// - `qty`: Filled quantity
+    pub fn record_fill(&mut self, order: &Order, qty: u64) {
+        *self
+            .fills
+            .entry((order.user, order.user_order_id))
+            .or_insert(0) += qty;
+    }
+
+    /// Check all invariants after a message was processed
+    ///
+    /// # Args
+    /// - `book`: Order book that processed the message
+    /// - `message`: Message that was processed
+    ///
+    /// # Return
+    /// - A description of the first violated invariant, if any
+    pub fn check(&mut self, book: &OrderBook, message: &Order) -> Result<(), String> {
+        let mut resting = HashMap::new();
+        for (side, orders) in [(Side::Buy, &book.bid_book), (Side::Sell, &book.ask_book)] {
+            check_side(&side, orders)?;
+            for order in orders.values().flatten() {
+                let key = (order.user, order.user_order_id);
+                if resting.insert(key, order.qty).is_some() {
+                    return Err(format!("Order id {:?} is not unique", key));
+                }
+                if book.resting.get(&key) != Some(&(side.clone(), order.price)) {
+                    return Err(format!("Order {:?} is not indexed at its level", key));
+                }
+            }
+        }
+        if book.resting.len() != resting.len() {
+            return Err("Index contains orders that left the book".to_string());
+        }
+        let top = |(price, orders): (&Price, &Vec<Order>)| (*price, total_qty(orders));
+        if book.highest_bid != book.bid_book.iter().next_back().map(top) {
+            return Err("Highest bid does not match the bid book".to_string());
+        }
+        if book.lowest_ask != book.ask_book.iter().next().map(top) {
+            return Err("Lowest ask does not match the ask book".to_string());
+        }
+        let crossed = book.symbols().into_iter().find(|symbol| {
+            let bid = book.best_price(&Side::Buy, symbol);
+            let ask = book.best_price(&Side::Sell, symbol);
+            matches!((bid, ask), (Some(bid), Some(ask)) if bid >= ask)
+        });
+        // Opening the session uncrosses the book. Without trading, crossing orders are rejected
+        // during continuous trading. Trading only matches orders of the same quantity, so the book
+        // may cross with trading, but a new order must not rest if it could have been matched.
+        let opened = self.state != Some(session::State::Open);
+        if crossed.is_some() && book.state == session::State::Open && (opened || !book.match_orders)
+        {
+            return Err(format!("Book is crossed in {:?}", crossed));
+        }
+        let key = (message.user, message.user_order_id);
+        if book.state == session::State::Open
+            && book.match_orders
+            && matches!(message.kind, Kind::New)
+            && !self.resting.contains_key(&key)
+        {
+            let order = book
+                .find_resting_order(message)
+                .map(|(price, pos, side)| match side {
+                    Side::Buy => &book.bid_book[&price][pos],
+                    Side::Sell => &book.ask_book[&price][pos],
+                });
+            if order.is_some_and(|order| book.find_match(order).is_some()) {
+                return Err(format!("Order {:?} rests although it matches", key));
+            }
+        }
+        self.state = Some(book.state);
+        let result = self.check_conservation(&resting, message);
+        self.resting = resting;
+        self.fills.clear();
+        result
+    }
+
+    /// Check that the quantity of the orders that rested before and after the message changed
+    /// only by fills and by the message itself
+    fn check_conservation(
+        &mut self,
+        resting: &HashMap<Key, u64>,
+        message: &Order,
+    ) -> Result<(), String> {
+        let key = (message.user, message.user_order_id);
+        for (order, previous) in &self.resting {
+            let filled = self.fills.remove(order).unwrap_or(0);
+            let amended = matches!(message.kind, Kind::Amend) && *order == key;
+            // An amendment that trades is filled with its new quantity
+            let previous = match amended && filled > 0 {
+                true => message.qty,
+                false => *previous,
+            };
+            let expected = previous
+                .checked_sub(filled)
+                .ok_or_else(|| format!("Order {:?} filled {} of {}", order, filled, previous))?;
+            match resting.get(order) {
+                Some(qty) if *qty != expected && !amended => {
+                    return Err(format!(
+                        "Order {:?} has quantity {} instead of {}",
+                        order, qty, expected
+                    ));
+                }
+                None if expected > 0 && !removes(message, order) => {
+                    return Err(format!("Order {:?} left the book unfilled", order));
+                }
+                _ => (),
+            }
+        }
+        // Only a new order may fill without resting before and enter the book
+        let filled = self.fills.remove(&key).unwrap_or(0);
+        if let Some(order) = self.fills.keys().next() {
+            return Err(format!("Order {:?} filled without resting", order));
+        }
+        let entered = resting
+            .get(&key)
+            .filter(|_| !self.resting.contains_key(&key))
+            .copied();
+        match message.kind {
+            Kind::New if filled + entered.unwrap_or(0) > message.qty => Err(format!(
+                "Order {:?} filled {} and rests {:?} of {}",
+                key, filled, entered, message.qty
+            )),
+            Kind::New => Ok(()),
+            _ if filled > 0 => Err(format!("Order {:?} filled without resting", key)),
+            _ => match resting
+                .keys()
+                .find(|order| !self.resting.contains_key(order))
+            {
+                Some(order) => Err(format!("Order {:?} entered the book unexpectedly", order)),
+                None => Ok(()),
+            },
+        }
+    }
+}
+
+/// Check the structure of one side of the book
+///
+/// # Args
+/// - `side`: Side of the orders in the book
+/// - `orders`: Orders by price
+///
+/// # Return
+/// - A description of the first violated invariant, if any
+fn check_side(side: &Side, orders: &BTreeMap<Price, Vec<Order>>) -> Result<(), String> {
+    for (price, bucket) in orders {
+        if bucket.is_empty() {
+            return Err(format!("Empty bucket at price {}", price));
+        }
+        for order in bucket {
+            let key = (order.user, order.user_order_id);
+            if order.price != *price || order.side != *side {
+                return Err(format!("Order {:?} is in the wrong bucket", key));
+            }
+            if order.qty == 0 {
+                return Err(format!("Order {:?} rests without quantity", key));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Checks if a message may remove a resting order from the book without a fill
+///
+/// # Args
+/// - `message`: Message that was processed
+/// - `order`: User and order id of the resting order
+///
+/// # Return
+/// - `true` if the message may remove the order, `false` otherwise
+fn removes(message: &Order, order: &Key) -> bool {
+    // Event timestamps advance the clock and expire good-till-date orders
+    message.timestamp.is_some()
+        || match &message.kind {
+            Kind::Cancel => *order == (message.user, message.user_order_id),
+            Kind::MassCancel(_) | Kind::Flush | Kind::Transition(_) => true,
+            Kind::New | Kind::Amend | Kind::Limits(..) | Kind::Statistics => false,
+        }
+}
@@ -0,0 +1,163 @@
+//! Defines the JSON Lines input and output formats, which use one self-describing JSON object
+//! per line instead of positional CSV fields
+
+use super::event::{self, Event};
+use super::order::Order;
+use csv::StringRecord;
+use serde_json::{Map, Value};
+
+static JSON_ERROR_MSG: &str = "Malformed JSON! Check your input file and try again.";
+
+/// Create a new order by interpreting one line of the input, see `parse_object`
+///
+/// # Args
+/// - `line`: One line of the input containing a JSON object
+///
+/// # Return
+/// - A new `Order` representing the input data
+///
+/// # Panics
+/// - If the line is malformed, see `parse_line` for a fallible alternative
+pub fn parse_order(line: &str) -> Order {
+    parse_line(line).expect(JSON_ERROR_MSG)
+}
+
+/// Interpret one line of untrusted input as order, see `parse_object`
+///
+/// # Args
+/// - `line`: One line of the input containing a JSON object
+///
+/// # Return
+/// - A new `Order` representing the input data or `None` if the line is malformed
+pub fn parse_line(line: &str) -> Option<Order> {
+    serde_json::from_str::<Value>(line)
+        .ok()
+        .and_then(|value| parse_object(value.as_object()?))
+}
+
+/// Create a new order by interpreting a JSON object. The field `type` selects the kind of the
+/// order, the other fields carry the same values as the fields of the CSV input:
+/// - `new`: `user`, `symbol`, `price`, `qty`, `side`, `order_id` and optional `time_in_force`
+/// - `cancel`: `user` and `order_id`
+/// - `amend`: `user`, `order_id`, `price` and `qty`
+/// - `mass_cancel`: optional `user`, `side`, `min_price` and `max_price`
+/// - `flush` and `statistics` without fields
+/// - `state`: `state`
+/// - `limits`: `user`, `max_order_qty`, `max_notional`, `max_open_orders` and
+///   `max_net_position`, where a missing user sets the default limits and a missing limit means
+///   there is no limit
+///
+/// Every object can carry an event timestamp in the field `timestamp`. Values can be given as
+/// JSON numbers or strings, sides as `B`/`buy` or `S`/`sell`.
+///
+/// # Args
+/// - `object`: JSON object representing the input data
+///
+/// # Return
+/// - A new `Order` representing the input data or `None` if the object is malformed
+pub fn parse_object(object: &Map<String, Value>) -> Option<Order> {
+    let optional = |name: &str, default: &str| match object.get(name) {
+        None | Some(Value::Null) => default.to_string(),
+        Some(Value::String(text)) => text.clone(),
+        Some(other) => other.to_string(),
+    };
+    let required = |name: &str| Some(optional(name, "")).filter(|value| !value.is_empty());
+    let side = |name: &str, default: &str| match optional(name, default).as_str() {
+        "B" | "buy" => Some("B".to_string()),
+        "S" | "sell" => Some("S".to_string()),
+        "*" => Some("*".to_string()),
+        _ => None,
+    };
+    let mut fields = match required("type")?.as_str() {
+        "new" => {
+            let mut fields = vec![
+                "N".to_string(),
+                required("user")?,
+                required("symbol")?,
+                required("price")?,
+                required("qty")?,
+                side("side", "")?,
+                required("order_id")?,
+            ];
+            if object.contains_key("time_in_force") {
+                fields.push(required("time_in_force")?);
+            }
+            fields
+        }
+        "cancel" => vec!["C".to_string(), required("user")?, required("order_id")?],
+        "amend" => vec![
+            "A".to_string(),
+            required("user")?,
+            required("order_id")?,
+            required("price")?,
+            required("qty")?,
+        ],
+        "mass_cancel" => vec![
+            "M".to_string(),
+            optional("user", "*"),
+            side("side", "*")?,
+            optional("min_price", "*"),
+            optional("max_price", "*"),
+        ],
+        "flush" => vec!["F".to_string()],
+        "statistics" => vec!["Q".to_string()],
+        "state" => vec!["S".to_string(), required("state")?],
+        "limits" => vec![
+            "L".to_string(),
+            optional("user", "*"),
+            optional("max_order_qty", "-"),
+            optional("max_notional", "-"),
+            optional("max_open_orders", "-"),
+            optional("max_net_position", "-"),
+        ],
+        _ => return None,
+    };
+    if object.contains_key("timestamp") {
+        fields.push(format!("@{}", required("timestamp")?));
+    }
+    Order::parse(&StringRecord::from(fields))
+}
+
+/// Format an output event as JSON object with named fields. Every object carries the fields
+/// `type`, `sequence` and `timestamp`, the separator of a flush has the type `flush`. Values that
+/// are not available are written as `null`.
+///
+/// # Args
+/// - `event`: Event to be formatted
+///
+/// # Return
+/// - The JSON object as one line
+pub fn format_event(event: &Event) -> String {
+    event_object(event).to_string()
+}
+
+/// Convert an output event into a JSON object with named fields, see `format_event`
+///
+/// # Args
+/// - `event`: Event to be converted
+///
+/// # Return
+/// - The JSON object
+pub fn event_object(event: &Event) -> Value {
+    let mut object = Map::new();
+    object.insert("sequence".to_string(), Value::from(event.sequence));
+    object.insert("timestamp".to_string(), Value::from(event.timestamp));
+    match event.message.schema() {
+        Some(schema) => {
+            object.insert("type".to_string(), Value::from(schema.name));
+            for ((field, _), value) in schema.fields.iter().zip(event.message.values()) {
+                let value = match value {
+                    event::Value::Number(number) => Value::from(number),
+                    event::Value::Price(price) => Value::from(price.to_string()),
+                    event::Value::Text(text) => Value::from(text),
+                    event::Value::Missing => Value::Null,
+                };
+                object.insert(field.to_string(), value);
+            }
+        }
+        None => {
+            object.insert("type".to_string(), Value::from("flush"));
+        }
+    }
+    Value::Object(object)
+}
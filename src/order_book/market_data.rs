@@ -0,0 +1,176 @@
+//! Defines the market data an order book publishes per symbol, which consists of changes of the
+//! top of book, changes of the price levels and trades
+
+use super::order::Side;
+use super::price::Price;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::mpsc::Sender;
+
+/// One change of the market data of a symbol
+pub enum Update {
+    /// Best price and total quantity of one side changed, `None` if the side became empty
+    TopOfBook {
+        symbol: String,
+        side: Side,
+        level: Option<(Price, u64)>,
+    },
+    /// Total quantity and number of orders of a price level changed, both are zero if the level
+    /// was removed
+    Level {
+        symbol: String,
+        side: Side,
+        price: Price,
+        qty: u64,
+        orders: u64,
+    },
+    /// Two orders traded
+    Trade {
+        symbol: String,
+        trade_id: u64,
+        timestamp: u64,
+        price: Price,
+        qty: u64,
+        /// Side of the incoming order, `None` for trades of an uncross
+        aggressor: Option<Side>,
+    },
+}
+
+/// Derives market data updates from the changes of an order book and sends them to another thread
+pub struct Feed {
+    sender: Sender<Update>,
+    /// Total quantity and number of orders of the price levels of every symbol and side as
+    /// published last
+    levels: HashMap<(String, Side), BTreeMap<Price, (u64, u64)>>,
+}
+
+impl Feed {
+    /// Factory function for constructing a new Feed
+    ///
+    /// # Args
+    /// - `sender`: MPSC sender the updates are sent to
+    ///
+    /// # Return
+    /// A new `Feed` instance
+    pub fn new(sender: Sender<Update>) -> Feed {
+        Feed {
+            sender,
+            levels: HashMap::new(),
+        }
+    }
+
+    /// Publish the touched price levels that changed, sorted by symbol, side and price. The top of
+    /// book of every touched symbol and side is derived from its levels and published after all
+    /// levels if it changed.
+    ///
+    /// # Args
+    /// - `touched`: Price levels an order entered, changed or left, by symbol, side and price
+    /// - `level`: Total quantity and number of orders of a price level of a symbol in the book
+    pub fn publish<F: Fn(&str, &Side, Price) -> (u64, u64)>(
+        &mut self,
+        touched: &BTreeSet<(String, Side, Price)>,
+        level: F,
+    ) {
+        let sides: BTreeSet<(String, Side)> = touched
+            .iter()
+            .map(|(symbol, side, _)| (symbol.clone(), side.clone()))
+            .collect();
+        let tops: Vec<_> = sides
+            .into_iter()
+            .map(|(symbol, side)| {
+                let top = self.top(&symbol, &side);
+                (symbol, side, top)
+            })
+            .collect();
+        for (symbol, side, price) in touched {
+            let (qty, orders) = level(symbol, side, *price);
+            let key = (symbol.clone(), side.clone());
+            let price = *price;
+            let levels = self.levels.entry(key.clone()).or_default();
+            let changed = match qty {
+                0 => levels.remove(&price).is_some(),
+                _ => levels.insert(price, (qty, orders)) != Some((qty, orders)),
+            };
+            if levels.is_empty() {
+                self.levels.remove(&key);
+            }
+            if changed {
+                let (symbol, side) = key;
+                self.send(Update::Level {
+                    symbol,
+                    side,
+                    price,
+                    qty,
+                    orders,
+                });
+            }
+        }
+        for (symbol, side, previous) in tops {
+            let level = self.top(&symbol, &side);
+            if level != previous {
+                self.send(Update::TopOfBook {
+                    symbol,
+                    side,
+                    level,
+                });
+            }
+        }
+    }
+
+    /// Get the best price and its total quantity of one side of a symbol as published last
+    ///
+    /// # Args
+    /// - `symbol`: Symbol of the orders
+    /// - `side`: Side of the book
+    ///
+    /// # Return
+    /// - Best price and total quantity, `None` if the side is empty
+    fn top(&self, symbol: &str, side: &Side) -> Option<(Price, u64)> {
+        let levels = self.levels.get(&(symbol.to_string(), side.clone()))?;
+        let (price, (qty, _)) = match side {
+            Side::Buy => levels.iter().next_back()?,
+            Side::Sell => levels.iter().next()?,
+        };
+        Some((*price, *qty))
+    }
+
+    /// Publish a trade
+    ///
+    /// # Args
+    /// - `symbol`: Symbol of the traded orders
+    /// - `trade_id`: Identifier of the trade
+    /// - `timestamp`: Time of the trade
+    /// - `price`: Price of the trade
+    /// - `qty`: Traded quantity
+    /// - `aggressor`: Side of the incoming order, `None` for trades of an uncross
+    pub fn trade(
+        &mut self,
+        symbol: &str,
+        trade_id: u64,
+        timestamp: u64,
+        price: Price,
+        qty: u64,
+        aggressor: Option<&Side>,
+    ) {
+        self.send(Update::Trade {
+            symbol: symbol.to_string(),
+            trade_id,
+            timestamp,
+            price,
+            qty,
+            aggressor: aggressor.cloned(),
+        });
+    }
+
+    /// Send an update, which is dropped if nobody receives the market data anymore
+    fn send(&self, update: Update) {
+        let _ = self.sender.send(update);
+    }
+}
+
+/// Get the index of a side in arrays that hold the buy side first
+pub fn side_index(side: &Side) -> usize {
+    match side {
+        Side::Buy => 0,
+        Side::Sell => 1,
+    }
+}
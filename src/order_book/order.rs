@@ -1,29 +1,114 @@
 //! Defines the order data type
 
-use csv::StringRecord;
+use super::price::Price;
+use super::risk::{self, Limits};
+use super::session::State;
+use csv::{ReaderBuilder, StringRecord, Trim};
 static CSV_ERROR_MSG: &str = "Malformed csv! Check your input file and try again.";
 
 /// Data structure to represent one order
+#[derive(Clone)]
 pub struct Order {
     pub kind: Kind,
-    pub user: i32,
-    pub price: i32,
-    pub qty: i32,
+    pub user: u64,
+    pub symbol: String,
+    pub price: Price,
+    pub qty: u64,
     pub side: Side,
-    pub user_order_id: i32,
+    pub user_order_id: u64,
+    pub time_in_force: TimeInForce,
+    /// Network session the order was entered by, `None` for orders read from a file
+    pub session: Option<u64>,
+    /// Event timestamp of the input record, if it carried one
+    pub timestamp: Option<u64>,
 }
 
 /// Enumeration to specify the side of the order book
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
 pub enum Side {
     Buy,
     Sell,
 }
 
+impl Side {
+    /// Get the other side of the book
+    pub fn opposite(&self) -> Side {
+        match self {
+            Side::Buy => Side::Sell,
+            Side::Sell => Side::Buy,
+        }
+    }
+}
+
+/// Enumeration to specify how long an order stays in the book
+#[derive(Clone, PartialEq)]
+pub enum TimeInForce {
+    /// Order expires when the session closes
+    Day,
+    /// Order stays in the book until it is cancelled
+    GoodTillCancel,
+    /// Order expires when the clock passes the given timestamp
+    GoodTillDate(u64),
+}
+
 /// Enumeration to specify the order kind
+#[derive(Clone)]
 pub enum Kind {
     New,
     Cancel,
+    /// Change the price and quantity of a resting order
+    Amend,
     Flush,
+    /// Move the trading session into another state
+    Transition(State),
+    /// Replace the risk limits of a user, or the default limits if no user is given
+    Limits(Option<u64>, Limits),
+    /// Cancel all resting orders matching the filter
+    MassCancel(CancelFilter),
+    /// Send a report of the statistics of the order book to the output
+    Statistics,
+}
+
+/// Filter to select resting orders for a mass cancellation. Criteria that are `None` match every
+/// order.
+#[derive(Clone, Default)]
+pub struct CancelFilter {
+    pub user: Option<u64>,
+    pub side: Option<Side>,
+    pub min_price: Option<Price>,
+    pub max_price: Option<Price>,
+    pub session: Option<u64>,
+}
+
+impl CancelFilter {
+    /// Checks if an order matches all criteria of the filter
+    ///
+    /// # Args
+    /// - `order`: Order to be checked
+    ///
+    /// # Return
+    /// - `true` if the order matches, `false` otherwise
+    pub fn matches(&self, order: &Order) -> bool {
+        self.user.is_none_or(|user| order.user == user)
+            && self.side.as_ref().is_none_or(|side| order.side == *side)
+            && self.min_price.is_none_or(|price| order.price >= price)
+            && self.max_price.is_none_or(|price| order.price <= price)
+            && self
+                .session
+                .is_none_or(|session| order.session == Some(session))
+    }
+}
+
+/// Create the configuration of the CSV reader for input records, which are headerless records of
+/// varying length with trimmed fields. Lines starting with `#` are comments.
+pub fn csv_reader() -> ReaderBuilder {
+    let mut builder = ReaderBuilder::new();
+    builder
+        .comment(Some(b'#'))
+        .has_headers(false)
+        .flexible(true)
+        .trim(Trim::All);
+    builder
 }
 
 impl Order {
@@ -34,15 +119,67 @@ impl Order {
     ///
     /// # Return
     /// - A new `Order` representing the input data
+    ///
+    /// # Panics
+    /// - If the record is malformed, see `Order::parse` for a fallible alternative
     pub fn from(record: &StringRecord) -> Order {
-        match record.get(0) {
-            Some(x) => match x {
-                "N" => Order::new_user_order(record),
-                "C" => Order::new_cancellation(record),
-                "F" => Order::new_flush(),
-                &_ => panic!("{}", CSV_ERROR_MSG),
-            },
-            None => panic!("{}", CSV_ERROR_MSG),
+        Order::parse(record).expect(CSV_ERROR_MSG)
+    }
+
+    /// Interpret a CSV record of untrusted input as order
+    ///
+    /// # Args
+    /// - `record`: One CSV record representing one order
+    ///
+    /// # Return
+    /// - A new `Order` representing the input data or `None` if the record is malformed
+    pub fn parse(record: &StringRecord) -> Option<Order> {
+        // Records may carry an event timestamp as additional last field in the format `@timestamp`
+        match record.iter().next_back().and_then(|x| x.strip_prefix('@')) {
+            Some(timestamp) => {
+                let timestamp = timestamp.parse::<u64>().ok()?;
+                let mut record = record.clone();
+                record.truncate(record.len() - 1);
+                let mut order = Order::from_fields(&record)?;
+                order.timestamp = Some(timestamp);
+                Some(order)
+            }
+            None => Order::from_fields(record),
+        }
+    }
+
+    /// Create a new order by interpreting the fields of a CSV record without event timestamp
+    ///
+    /// # Args
+    /// - `record`: One CSV record representing the input data
+    ///
+    /// # Return
+    /// - A new `Order` representing the input data or `None` if the record is malformed
+    fn from_fields(record: &StringRecord) -> Option<Order> {
+        let order = match record.get(0)? {
+            "N" => Order::new_user_order(record)?,
+            "C" => Order::new_cancellation(record)?,
+            "A" => Order::new_amendment(record)?,
+            "F" => Order::new_flush(),
+            "S" => Order::new_transition(record)?,
+            "M" => Order::new_mass_cancellation(record)?,
+            "L" => {
+                let (user, limits) = risk::parse_limits(record)?;
+                Order::new_command(Kind::Limits(user, limits))
+            }
+            "P" => Order::new_command(Kind::Transition(State::PreOpen)),
+            "U" => Order::new_command(Kind::Transition(State::Open)),
+            "Q" => Order::new_command(Kind::Statistics),
+            &_ => return None,
+        };
+        Some(order)
+    }
+
+    /// Get the timestamp an order with time in force good-till-date expires at
+    pub fn expiry(&self) -> Option<u64> {
+        match self.time_in_force {
+            TimeInForce::GoodTillDate(expiry) => Some(expiry),
+            TimeInForce::Day | TimeInForce::GoodTillCancel => None,
         }
     }
 
@@ -52,40 +189,30 @@ impl Order {
     /// - `record`: One CSV record representing a new user order
     ///
     /// # Return
-    /// - A new `Order` representing the input data
-    fn new_user_order(record: &StringRecord) -> Order {
-        let side = match record.get(5) {
-            Some(x) => match x {
-                "B" => Side::Buy,
-                "S" => Side::Sell,
-                &_ => panic!("{}", CSV_ERROR_MSG),
-            },
-            None => panic!("{}", CSV_ERROR_MSG),
+    /// - A new `Order` representing the input data or `None` if the record is malformed
+    fn new_user_order(record: &StringRecord) -> Option<Order> {
+        let side = match record.get(5)? {
+            "B" => Side::Buy,
+            "S" => Side::Sell,
+            &_ => return None,
         };
-        Order {
+        let time_in_force = match record.get(7) {
+            Some("DAY") | None => TimeInForce::Day,
+            Some("GTC") => TimeInForce::GoodTillCancel,
+            Some(x) => TimeInForce::GoodTillDate(x.strip_prefix("GTD:")?.parse().ok()?),
+        };
+        Some(Order {
             kind: Kind::New,
-            user: record
-                .get(1)
-                .expect(CSV_ERROR_MSG)
-                .parse::<i32>()
-                .expect(CSV_ERROR_MSG),
-            price: record
-                .get(3)
-                .expect(CSV_ERROR_MSG)
-                .parse::<i32>()
-                .expect(CSV_ERROR_MSG),
-            qty: record
-                .get(4)
-                .expect(CSV_ERROR_MSG)
-                .parse::<i32>()
-                .expect(CSV_ERROR_MSG),
+            user: record.get(1)?.parse::<u64>().ok()?,
+            symbol: record.get(2)?.to_string(),
+            price: record.get(3)?.parse::<Price>().ok()?,
+            qty: record.get(4)?.parse::<u64>().ok()?,
             side,
-            user_order_id: record
-                .get(6)
-                .expect(CSV_ERROR_MSG)
-                .parse::<i32>()
-                .expect(CSV_ERROR_MSG),
-        }
+            user_order_id: record.get(6)?.parse::<u64>().ok()?,
+            time_in_force,
+            session: None,
+            timestamp: None,
+        })
     }
 
     /// Create a new cancellation order by interpreting the CSV record
@@ -94,35 +221,108 @@ impl Order {
     /// - `record`: One CSV record representing one cancellation order
     ///
     /// # Return
-    /// - A new `Order` representing the input data
-    fn new_cancellation(record: &StringRecord) -> Order {
-        Order {
-            kind: Kind::Cancel,
-            user: record
-                .get(1)
-                .expect(CSV_ERROR_MSG)
-                .parse::<i32>()
-                .expect(CSV_ERROR_MSG),
-            price: 0,
-            qty: 0,
-            side: Side::Buy,
-            user_order_id: record
-                .get(2)
-                .expect(CSV_ERROR_MSG)
-                .parse::<i32>()
-                .expect(CSV_ERROR_MSG),
-        }
+    /// - A new `Order` representing the input data or `None` if the record is malformed
+    fn new_cancellation(record: &StringRecord) -> Option<Order> {
+        let mut order = Order::new_command(Kind::Cancel);
+        order.user = record.get(1)?.parse::<u64>().ok()?;
+        order.user_order_id = record.get(2)?.parse::<u64>().ok()?;
+        Some(order)
+    }
+
+    /// Create a new amendment by interpreting the CSV record
+    ///
+    /// # Args
+    /// - `record`: One CSV record representing one amendment
+    ///
+    /// # Return
+    /// - A new `Order` representing the input data or `None` if the record is malformed
+    fn new_amendment(record: &StringRecord) -> Option<Order> {
+        let mut order = Order::new_command(Kind::Amend);
+        order.user = record.get(1)?.parse::<u64>().ok()?;
+        order.user_order_id = record.get(2)?.parse::<u64>().ok()?;
+        order.price = record.get(3)?.parse::<Price>().ok()?;
+        order.qty = record.get(4)?.parse::<u64>().ok()?;
+        Some(order)
+    }
+
+    /// Create a new mass cancellation by interpreting the CSV record. Every criterion of the
+    /// filter is optional and can be given as `*` to match every order.
+    ///
+    /// # Args
+    /// - `record`: One CSV record representing one mass cancellation
+    ///
+    /// # Return
+    /// - A new `Order` representing the input data or `None` if the record is malformed
+    fn new_mass_cancellation(record: &StringRecord) -> Option<Order> {
+        let criterion = |i: usize| record.get(i).filter(|x| *x != "*");
+        let price = |i: usize| criterion(i).map(str::parse::<Price>).transpose().ok();
+        let filter = CancelFilter {
+            user: criterion(1).map(str::parse::<u64>).transpose().ok()?,
+            side: criterion(2)
+                .map(|x| match x {
+                    "B" => Ok(Side::Buy),
+                    "S" => Ok(Side::Sell),
+                    &_ => Err(()),
+                })
+                .transpose()
+                .ok()?,
+            min_price: price(3)?,
+            max_price: price(4)?,
+            session: None,
+        };
+        Some(Order::new_command(Kind::MassCancel(filter)))
+    }
+
+    /// Create a new mass cancellation of all orders entered by a network session
+    ///
+    /// # Args
+    /// - `session`: Network session to cancel the orders of
+    ///
+    /// # Return
+    /// - A new `Order` representing the mass cancellation
+    pub fn new_session_cancellation(session: u64) -> Order {
+        Order::new_command(Kind::MassCancel(CancelFilter {
+            session: Some(session),
+            ..CancelFilter::default()
+        }))
+    }
+
+    /// Create a new session transition by interpreting the CSV record
+    ///
+    /// # Args
+    /// - `record`: One CSV record representing a session transition
+    ///
+    /// # Return
+    /// - A new `Order` representing the input data or `None` if the record is malformed
+    fn new_transition(record: &StringRecord) -> Option<Order> {
+        let state = record.get(1)?.parse::<State>().ok()?;
+        Some(Order::new_command(Kind::Transition(state)))
     }
 
     /// Create a new flush order
     fn new_flush() -> Order {
+        Order::new_command(Kind::Flush)
+    }
+
+    /// Create a new order that carries no user data, but only a command for the order book
+    ///
+    /// # Args
+    /// - `kind`: Kind of the command
+    ///
+    /// # Return
+    /// - A new `Order` representing the command
+    pub fn new_command(kind: Kind) -> Order {
         Order {
-            kind: Kind::Flush,
+            kind,
             user: 0,
-            price: 0,
+            symbol: String::new(),
+            price: Price::default(),
             qty: 0,
             side: Side::Buy,
             user_order_id: 0,
+            time_in_force: TimeInForce::Day,
+            session: None,
+            timestamp: None,
         }
     }
 }
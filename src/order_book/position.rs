@@ -0,0 +1,169 @@
+//! Defines a ledger of positions and profit and loss per user, built from executed trades
+
+use super::order::Side;
+use super::price::{self, Price};
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryFrom;
+use std::io::{self, Write};
+
+/// Position of one user in one symbol. Amounts are multiples of `10^-price::DECIMALS` and saturate
+/// instead of overflowing.
+#[derive(Clone, Default)]
+pub struct Position {
+    /// Net quantity, positive for long and negative for short positions
+    pub net_qty: i128,
+    /// Entry value of the net quantity, which has the same sign as the net quantity
+    pub cost: i128,
+    /// Profit and loss of all closed quantities
+    pub realized_pnl: i128,
+}
+
+impl Position {
+    /// Get the average entry price of the net quantity
+    ///
+    /// # Return
+    /// - The average entry price or `None` if the position is flat
+    pub fn avg_price(&self) -> Option<Price> {
+        if self.net_qty == 0 {
+            return None;
+        }
+        i64::try_from(self.cost / self.net_qty)
+            .ok()
+            .map(Price::from_units)
+    }
+
+    /// Determine the profit and loss of the net quantity if it was closed at a given price
+    ///
+    /// # Args
+    /// - `mark`: Price to value the net quantity at
+    ///
+    /// # Return
+    /// - The unrealized profit and loss
+    pub fn unrealized_pnl(&self, mark: Price) -> i128 {
+        i128::from(mark.units())
+            .saturating_mul(self.net_qty)
+            .saturating_sub(self.cost)
+    }
+
+    /// Update the position with a fill. Quantity that reduces the position realizes profit and
+    /// loss against the average entry price, quantity that exceeds it opens a new position.
+    ///
+    /// # Args
+    /// - `side`: Side of the user in the trade
+    /// - `price`: Price of the trade
+    /// - `qty`: Traded quantity
+    fn fill(&mut self, side: &Side, price: Price, qty: u64) {
+        let price = i128::from(price.units());
+        let mut qty = match side {
+            Side::Buy => i128::from(qty),
+            Side::Sell => -i128::from(qty),
+        };
+        // Close existing quantity on the opposite side first
+        if self.net_qty != 0 && self.net_qty.signum() != qty.signum() {
+            let closed = qty.abs().min(self.net_qty.abs()) * qty.signum();
+            // Split the cost into the average entry price and a remainder, so the cost of the
+            // closed quantity is exact without multiplying the whole cost by the quantity
+            let net_qty = self.net_qty.abs();
+            let closed_cost = (self.cost / net_qty)
+                .saturating_mul(closed.abs())
+                .saturating_add((self.cost % net_qty).saturating_mul(closed.abs()) / net_qty);
+            self.realized_pnl = self
+                .realized_pnl
+                .saturating_sub(price.saturating_mul(closed))
+                .saturating_sub(closed_cost);
+            self.cost = self.cost.saturating_sub(closed_cost);
+            self.net_qty += closed;
+            qty -= closed;
+        }
+        self.net_qty += qty;
+        self.cost = self.cost.saturating_add(price.saturating_mul(qty));
+    }
+}
+
+/// Keeps the positions of all users by symbol
+#[derive(Default)]
+pub struct Ledger {
+    /// Positions by user and symbol
+    positions: BTreeMap<(u64, String), Position>,
+    /// Last known mid price per symbol, used to value positions
+    marks: HashMap<String, Price>,
+}
+
+impl Ledger {
+    /// Update the positions of both users of a trade
+    ///
+    /// # Args
+    /// - `symbol`: Traded symbol
+    /// - `buyer`: User that bought
+    /// - `seller`: User that sold
+    /// - `price`: Price of the trade
+    /// - `qty`: Traded quantity
+    pub fn record_trade(&mut self, symbol: &str, buyer: u64, seller: u64, price: Price, qty: u64) {
+        for (user, side) in [(buyer, Side::Buy), (seller, Side::Sell)] {
+            self.positions
+                .entry((user, symbol.to_string()))
+                .or_default()
+                .fill(&side, price, qty);
+        }
+    }
+
+    /// Update the mid price a symbol is valued at
+    ///
+    /// # Args
+    /// - `symbol`: Symbol to update the mid price of
+    /// - `mid`: New mid price
+    pub fn update_mark(&mut self, symbol: &str, mid: Price) {
+        self.marks.insert(symbol.to_string(), mid);
+    }
+
+    /// Get the net quantity of a user in a symbol, which is 0 if the user never traded it
+    pub fn net_qty(&self, user: u64, symbol: &str) -> i128 {
+        self.positions
+            .get(&(user, symbol.to_string()))
+            .map_or(0, |position| position.net_qty)
+    }
+
+    /// Get the mid price a symbol is valued at, if it is known
+    pub fn mark(&self, symbol: &str) -> Option<Price> {
+        self.marks.get(symbol).copied()
+    }
+
+    /// Iterate over all positions as tuple of user, symbol and position, ordered by user and
+    /// symbol
+    pub fn iter(&self) -> impl Iterator<Item = (u64, &str, &Position)> {
+        self.positions
+            .iter()
+            .map(|((user, symbol), position)| (*user, symbol.as_str(), position))
+    }
+
+    /// Write a report of all positions in the CSV format
+    /// `P, user_id, symbol, net_quantity, average_price, realized_pnl, unrealized_pnl`. Values
+    /// that are not available, like the average price of a flat position, are written as `-`.
+    ///
+    /// # Args
+    /// - `writer`: Destination of the report
+    ///
+    /// # Return
+    /// An `io::Result` indicating if the report could be written
+    pub fn write_report<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        for (user, symbol, position) in self.iter() {
+            let avg_price = position
+                .avg_price()
+                .map_or("-".to_string(), |p| p.to_string());
+            let unrealized_pnl = self.mark(symbol).map_or("-".to_string(), |mark| {
+                price::format_units(position.unrealized_pnl(mark))
+            });
+            writeln!(
+                writer,
+                "P, {}, {}, {}, {}, {}, {}",
+                user,
+                symbol,
+                position.net_qty,
+                avg_price,
+                price::format_units(position.realized_pnl),
+                unrealized_pnl
+            )?;
+        }
+        Ok(())
+    }
+}
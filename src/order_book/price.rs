@@ -0,0 +1,111 @@
+//! Defines the fixed-point price type
+
+use serde::{Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+
+/// Number of decimals a price is stored with
+pub const DECIMALS: u32 = 8;
+
+/// Number of units per whole price, i.e. `10^DECIMALS`
+const UNITS_PER_WHOLE: i64 = 10_i64.pow(DECIMALS);
+
+/// Fixed-point price, stored as integer multiple of `10^-DECIMALS`
+#[derive(Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct Price(i64);
+
+impl Price {
+    /// Create a price from its integer representation
+    ///
+    /// # Args
+    /// - `units`: Price as multiple of `10^-DECIMALS`
+    ///
+    /// # Return
+    /// - A new `Price`
+    pub fn from_units(units: i64) -> Price {
+        Price(units)
+    }
+
+    /// Get the integer representation of the price
+    ///
+    /// # Return
+    /// - Price as multiple of `10^-DECIMALS`
+    pub fn units(self) -> i64 {
+        self.0
+    }
+
+    /// Checks if the price can be written with the given number of decimals
+    ///
+    /// # Args
+    /// - `decimals`: Maximum number of decimals
+    ///
+    /// # Return
+    /// - `true` if no more decimals are needed to write the price, `false` otherwise
+    pub fn fits_decimals(self, decimals: u32) -> bool {
+        decimals >= DECIMALS || self.0 % 10_i64.pow(DECIMALS - decimals) == 0
+    }
+}
+
+impl FromStr for Price {
+    type Err = String;
+
+    /// Interpret a decimal string such as `10`, `10.25` or `-0.5` as price
+    fn from_str(decimal: &str) -> Result<Price, String> {
+        let error = || format!("Invalid price {}", decimal);
+        let (negative, digits) = match decimal.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, decimal),
+        };
+        let (integer, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+        if integer.is_empty()
+            || fraction.len() > DECIMALS as usize
+            || !integer
+                .bytes()
+                .chain(fraction.bytes())
+                .all(|b| b.is_ascii_digit())
+        {
+            return Err(error());
+        }
+        let fraction = format!("{:0<width$}", fraction, width = DECIMALS as usize);
+        let units = integer
+            .parse::<i64>()
+            .ok()
+            .and_then(|i| i.checked_mul(UNITS_PER_WHOLE))
+            .and_then(|i| i.checked_add(fraction.parse::<i64>().ok()?))
+            .ok_or_else(error)?;
+        Ok(Price(if negative { -units } else { units }))
+    }
+}
+
+impl fmt::Display for Price {
+    /// Write the price as decimal string without trailing zeros, e.g. `10` or `10.25`
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", format_units(i128::from(self.0)))
+    }
+}
+
+impl Serialize for Price {
+    /// Serialize the price as decimal string to keep its precision, e.g. `"10.25"`
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// Write an amount with the precision of a price as decimal string without trailing zeros. Used
+/// for values that may exceed the range of a `Price`, such as the price times a quantity.
+///
+/// # Args
+/// - `units`: Amount as multiple of `10^-DECIMALS`
+///
+/// # Return
+/// - The decimal string, e.g. `10.25`
+pub fn format_units(units: i128) -> String {
+    let sign = if units < 0 { "-" } else { "" };
+    let integer = (units / i128::from(UNITS_PER_WHOLE)).unsigned_abs();
+    let fraction = (units % i128::from(UNITS_PER_WHOLE)).unsigned_abs();
+    if fraction == 0 {
+        return format!("{}{}", sign, integer);
+    }
+    let fraction = format!("{:0width$}", fraction, width = DECIMALS as usize);
+    format!("{}{}.{}", sign, integer, fraction.trim_end_matches('0'))
+}
@@ -0,0 +1,98 @@
+//! Defines price bands that protect the order book against orders with erroneous prices
+
+use super::price::Price;
+use super::session::State;
+
+/// Configuration of the static and dynamic price bands of an order book. Band widths are given in
+/// basis points of the price the band is centered around.
+#[derive(Clone)]
+pub struct PriceBands {
+    /// Center of the static band until the first uncross
+    pub reference_price: Option<Price>,
+    /// Width of the static band around the reference price
+    pub static_band: Option<u32>,
+    /// Width of the dynamic band around the last trade price
+    pub dynamic_band: Option<u32>,
+    /// Session state the order book moves into when a trade would print outside the dynamic band
+    pub volatility_state: State,
+}
+
+impl Default for PriceBands {
+    fn default() -> PriceBands {
+        PriceBands {
+            reference_price: None,
+            static_band: None,
+            dynamic_band: None,
+            volatility_state: State::PreOpen,
+        }
+    }
+}
+
+impl PriceBands {
+    /// Checks if the price of a new order lies within the price bands
+    ///
+    /// # Args
+    /// - `price`: Price of the new order
+    /// - `auction_price`: Price of the last uncross, which replaces the configured reference price
+    /// - `last_trade_price`: Price of the last trade, if there was one
+    ///
+    /// # Return
+    /// - The reason code for rejecting the order or `None` if the price is within the bands
+    pub fn check(
+        &self,
+        price: Price,
+        auction_price: Option<Price>,
+        last_trade_price: Option<Price>,
+    ) -> Option<&'static str> {
+        let reference_price = auction_price.or(self.reference_price);
+        if !within(reference_price, self.static_band, price) {
+            return Some("STATIC_BAND");
+        }
+        if !self.within_dynamic_band(price, last_trade_price) {
+            return Some("DYNAMIC_BAND");
+        }
+        None
+    }
+
+    /// Checks if a price lies within the dynamic band
+    ///
+    /// # Args
+    /// - `price`: Price to be checked
+    /// - `last_trade_price`: Price of the last trade, if there was one
+    ///
+    /// # Return
+    /// - `true` if the price is within the band or there is no band, `false` otherwise
+    pub fn within_dynamic_band(&self, price: Price, last_trade_price: Option<Price>) -> bool {
+        within(last_trade_price, self.dynamic_band, price)
+    }
+}
+
+/// Interpret the name of the session state the circuit breaker moves into, which is either
+/// `PREOPEN` or `HALTED`. Other states would disable the circuit breaker or end the day.
+///
+/// # Args
+/// - `name`: Name of the session state
+///
+/// # Return
+/// - The session state or an error for states that are not allowed
+pub fn parse_volatility_state(name: &str) -> Result<State, String> {
+    match name.parse()? {
+        state @ (State::PreOpen | State::Halted) => Ok(state),
+        _ => Err(format!(
+            "Volatility state must be PREOPEN or HALTED, not {}",
+            name
+        )),
+    }
+}
+
+/// Checks if a price lies within a band around a reference price. Without reference price or band
+/// width, every price is considered to be within the band.
+fn within(reference: Option<Price>, band: Option<u32>, price: Price) -> bool {
+    match (reference, band) {
+        (Some(reference), Some(band)) => {
+            let (price, reference) = (i128::from(price.units()), i128::from(reference.units()));
+            (price - reference).abs() * 10_000 <= reference.abs() * i128::from(band)
+        }
+        _ => true,
+    }
+}
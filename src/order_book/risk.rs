@@ -0,0 +1,183 @@
+//! Defines pre-trade risk checks that new orders have to pass per user
+
+use super::order::{Order, Side};
+use super::price::Price;
+use csv::StringRecord;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+static CONFIG_ERROR_MSG: &str = "Malformed risk limits! Check your config file and try again.";
+
+/// Risk limits of one user, `None` means there is no limit
+#[derive(Clone, Default)]
+pub struct Limits {
+    /// Maximum quantity of a single order
+    pub max_order_qty: Option<u64>,
+    /// Maximum price times quantity of a single order
+    pub max_notional: Option<Price>,
+    /// Maximum number of orders resting in the book
+    pub max_open_orders: Option<u64>,
+    /// Maximum absolute net position per symbol, including all resting orders on the same side
+    pub max_net_position: Option<u64>,
+}
+
+/// Net position of one user and the orders that are currently resting in the book
+#[derive(Default)]
+pub struct Exposure {
+    /// Net position in the symbol of the checked order
+    pub net_qty: i128,
+    /// Number of resting orders over all symbols
+    pub open_orders: u64,
+    /// Resting buy quantity in the symbol of the checked order
+    pub open_buy_qty: u64,
+    /// Resting sell quantity in the symbol of the checked order
+    pub open_sell_qty: u64,
+}
+
+/// Keeps the risk limits of all users
+#[derive(Clone, Default)]
+pub struct RiskManager {
+    /// Limits of users without own limits
+    default_limits: Limits,
+    user_limits: HashMap<u64, Limits>,
+}
+
+impl RiskManager {
+    /// Replace the limits of a user
+    ///
+    /// # Args
+    /// - `user`: User to set the limits for or `None` to set the default limits
+    /// - `limits`: New limits
+    pub fn set_limits(&mut self, user: Option<u64>, limits: Limits) {
+        match user {
+            Some(user) => {
+                self.user_limits.insert(user, limits);
+            }
+            None => self.default_limits = limits,
+        }
+    }
+
+    /// Get the limits that apply to a user
+    pub fn limits(&self, user: u64) -> &Limits {
+        self.user_limits.get(&user).unwrap_or(&self.default_limits)
+    }
+
+    /// Checks if a user needs to know about resting orders to check the limits, as collecting the
+    /// exposure requires to search the whole book
+    pub fn needs_exposure(&self, user: u64) -> bool {
+        let limits = self.limits(user);
+        limits.max_open_orders.is_some() || limits.max_net_position.is_some()
+    }
+
+    /// Checks a new order against the limits of its user
+    ///
+    /// # Args
+    /// - `order`: New order to be checked
+    /// - `exposure`: Net position of the user and orders that are currently resting in the book
+    ///
+    /// # Return
+    /// - The reason code for rejecting the order or `None` if the order is within the limits
+    pub fn check(&self, order: &Order, exposure: &Exposure) -> Option<&'static str> {
+        let limits = self.limits(order.user);
+        if limits.max_order_qty.is_some_and(|max| order.qty > max) {
+            return Some("RISK_ORDER_QTY");
+        }
+        if let Some(max) = limits.max_notional {
+            let notional = i128::from(order.price.units()).abs() * i128::from(order.qty);
+            if notional > i128::from(max.units()) {
+                return Some("RISK_NOTIONAL");
+            }
+        }
+        if limits
+            .max_open_orders
+            .is_some_and(|max| exposure.open_orders >= max)
+        {
+            return Some("RISK_OPEN_ORDERS");
+        }
+        if let Some(max) = limits.max_net_position {
+            let position = exposure.net_qty;
+            // Assume the worst case, in which all orders on the same side are filled
+            let position = match order.side {
+                Side::Buy => position + i128::from(exposure.open_buy_qty) + i128::from(order.qty),
+                Side::Sell => position - i128::from(exposure.open_sell_qty) - i128::from(order.qty),
+            };
+            if position.abs() > i128::from(max) {
+                return Some("RISK_POSITION");
+            }
+        }
+        None
+    }
+}
+
+/// Load risk limits from a CSV config file, which contains records in the format described for
+/// `parse_limits`
+///
+/// # Args
+/// - `path`: Path of the config file
+///
+/// # Return
+/// A `Result` containing a `RiskManager` with the limits or an `csv::Error`, if there is an issue
+/// with reading the provided CSV file.
+pub fn load(path: &Path) -> Result<RiskManager, csv::Error> {
+    read(File::open(path)?)
+}
+
+/// Read risk limits in the format described for `parse_limits`
+///
+/// # Args
+/// - `config`: Reader providing the content of a config file
+///
+/// # Return
+/// A `Result` containing a `RiskManager` with the limits or an `csv::Error`, if there is an issue
+/// with reading the provided CSV data.
+pub fn read<R: Read>(config: R) -> Result<RiskManager, csv::Error> {
+    let mut reader = csv::ReaderBuilder::new()
+        .comment(Some(b'#'))
+        .has_headers(false)
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(config);
+    let mut risk_manager = RiskManager::default();
+    for result in reader.records() {
+        let (user, limits) = parse_limits(&result?).expect(CONFIG_ERROR_MSG);
+        risk_manager.set_limits(user, limits);
+    }
+    Ok(risk_manager)
+}
+
+/// Interpret a CSV record in the format
+/// `L, user, max_order_qty, max_notional, max_open_orders, max_net_position`. The user `*` sets the
+/// default limits, a limit of `-` means there is no limit.
+///
+/// # Args
+/// - `record`: One CSV record representing the limits of a user
+///
+/// # Return
+/// - The user, `None` for the default limits, and the limits, or `None` if the record is
+///   malformed
+pub fn parse_limits(record: &StringRecord) -> Option<(Option<u64>, Limits)> {
+    if record.get(0)? != "L" {
+        return None;
+    }
+    let field = |i: usize| record.get(i);
+    let limit = |i: usize| match field(i)? {
+        "-" => Some(None),
+        x => x.parse::<u64>().ok().map(Some),
+    };
+    let user = match field(1)? {
+        "*" => None,
+        x => Some(x.parse::<u64>().ok()?),
+    };
+    let limits = Limits {
+        max_order_qty: limit(2)?,
+        max_notional: match field(3)? {
+            "-" => None,
+            x => Some(x.parse::<Price>().ok()?),
+        },
+        max_open_orders: limit(4)?,
+        max_net_position: limit(5)?,
+    };
+    Some((user, limits))
+}
@@ -0,0 +1,85 @@
+//! Defines the trading session states of an order book and the transitions between them
+
+use std::fmt;
+use std::str::FromStr;
+
+/// Enumeration to specify the trading session state of the order book
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum State {
+    /// Call auction phase, orders accumulate in the book without matching
+    PreOpen,
+    /// Continuous trading, orders are matched or rejected as soon as they cross the book
+    Open,
+    /// Trading is interrupted, only cancellations are accepted
+    Halted,
+    /// Trading has ended for the day, no messages are accepted
+    Closed,
+}
+
+impl State {
+    /// Checks if the session may move from this state into another one
+    ///
+    /// # Args
+    /// - `next`: State to move into
+    ///
+    /// # Return
+    /// - `true` if the transition is allowed, `false` otherwise
+    pub fn can_transition_to(self, next: State) -> bool {
+        match self {
+            State::PreOpen => matches!(next, State::Open | State::Halted | State::Closed),
+            State::Open => matches!(next, State::PreOpen | State::Halted | State::Closed),
+            State::Halted => matches!(next, State::PreOpen | State::Open | State::Closed),
+            State::Closed => matches!(next, State::PreOpen | State::Open),
+        }
+    }
+
+    /// Reason code used when a new order is rejected in this state
+    ///
+    /// # Return
+    /// - The reason code or `None` if new orders are accepted
+    pub fn new_order_reject_reason(self) -> Option<&'static str> {
+        match self {
+            State::PreOpen | State::Open => None,
+            State::Halted => Some("HALTED"),
+            State::Closed => Some("CLOSED"),
+        }
+    }
+
+    /// Reason code used when a cancellation is rejected in this state
+    ///
+    /// # Return
+    /// - The reason code or `None` if cancellations are accepted
+    pub fn cancel_reject_reason(self) -> Option<&'static str> {
+        match self {
+            State::PreOpen | State::Open | State::Halted => None,
+            State::Closed => Some("CLOSED"),
+        }
+    }
+}
+
+impl FromStr for State {
+    type Err = String;
+
+    /// Interpret the name of a session state as used in input and output files, e.g. `OPEN`
+    fn from_str(name: &str) -> Result<State, String> {
+        match name {
+            "PREOPEN" => Ok(State::PreOpen),
+            "OPEN" => Ok(State::Open),
+            "HALTED" => Ok(State::Halted),
+            "CLOSED" => Ok(State::Closed),
+            _ => Err(format!("Unknown session state {}", name)),
+        }
+    }
+}
+
+impl fmt::Display for State {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            State::PreOpen => "PREOPEN",
+            State::Open => "OPEN",
+            State::Halted => "HALTED",
+            State::Closed => "CLOSED",
+        };
+        write!(f, "{}", name)
+    }
+}
@@ -0,0 +1,165 @@
+//! Defines statistics and market quality metrics collected from the activity of an order book
+
+use super::event::Message;
+use super::order::Kind;
+use super::price::Price;
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+
+/// Price and volume of one side of the top of book
+type Level = (Price, u64);
+
+/// Activity of one user
+#[derive(Clone, Default)]
+pub struct UserStatistics {
+    /// Number of new orders, including rejected ones
+    pub orders: u64,
+    /// Quantity of all new orders
+    pub ordered_qty: u64,
+    /// Number of accepted cancellations and orders removed by mass cancellations
+    pub cancels: u64,
+    /// Number of rejected new orders and cancellations
+    pub rejects: u64,
+    /// Number of trades the user took part in
+    pub trades: u64,
+    pub traded_qty: u64,
+}
+
+impl UserStatistics {
+    /// Get the traded quantity relative to the quantity of all new orders
+    pub fn fill_ratio(&self) -> Option<f64> {
+        ratio(self.traded_qty, self.ordered_qty)
+    }
+
+    /// Get the number of cancellations per trade
+    pub fn cancel_to_trade_ratio(&self) -> Option<f64> {
+        ratio(self.cancels, self.trades)
+    }
+}
+
+/// Collects statistics over the lifetime of an order book. Time-weighted metrics are weighted
+/// with the time of the clock the output events are timestamped with.
+#[derive(Default)]
+pub struct Statistics {
+    users: BTreeMap<u64, UserStatistics>,
+    /// Top of book as tuple of time, best bid and best ask since the last change
+    top_of_book: (u64, Option<Level>, Option<Level>),
+    /// Sum of spread times duration while both sides were present
+    spread_time: i128,
+    /// Sum of bid volume and ask volume times duration while both sides were present
+    depth_time: (u128, u128),
+    /// Duration both sides were present
+    two_sided_time: u64,
+    /// Number of received messages by message type of the CSV input
+    messages: BTreeMap<&'static str, u64>,
+}
+
+impl Statistics {
+    /// Get the statistics of a user for updating them
+    pub fn user(&mut self, user: u64) -> &mut UserStatistics {
+        self.users.entry(user).or_default()
+    }
+
+    /// Iterate over the statistics of all users as tuple of user and statistics, ordered by user
+    pub fn users(&self) -> impl Iterator<Item = (u64, &UserStatistics)> {
+        self.users
+            .iter()
+            .map(|(user, statistics)| (*user, statistics))
+    }
+
+    /// Count a received message by its message type in the CSV input, where all state
+    /// transitions count as `S`
+    ///
+    /// # Args
+    /// - `kind`: Kind of the received message
+    pub fn record_message(&mut self, kind: &Kind) {
+        let message_type = match kind {
+            Kind::New => "N",
+            Kind::Cancel => "C",
+            Kind::Amend => "A",
+            Kind::Flush => "F",
+            Kind::Transition(_) => "S",
+            Kind::Limits(..) => "L",
+            Kind::MassCancel(_) => "M",
+            Kind::Statistics => "Q",
+        };
+        *self.messages.entry(message_type).or_insert(0) += 1;
+    }
+
+    /// Record the top of book after a change. The previous top of book is weighted with the time
+    /// since the last change.
+    ///
+    /// # Args
+    /// - `time`: Current time
+    /// - `bid`: Price and volume of the highest bid, if there is one
+    /// - `ask`: Price and volume of the lowest ask, if there is one
+    pub fn observe_top_of_book(&mut self, time: u64, bid: Option<Level>, ask: Option<Level>) {
+        let (since, previous_bid, previous_ask) = self.top_of_book;
+        // The logical clock never moves backwards, but the wall clock may be set back
+        let duration = time.saturating_sub(since);
+        if let (Some((bid, bid_volume)), Some((ask, ask_volume))) = (previous_bid, previous_ask) {
+            let spread = i128::from(ask.units()) - i128::from(bid.units());
+            // Sums saturate for extreme prices and volumes over long runs instead of overflowing
+            let weighted = |value: u64| u128::from(value).saturating_mul(u128::from(duration));
+            self.spread_time = self
+                .spread_time
+                .saturating_add(spread.saturating_mul(i128::from(duration)));
+            self.depth_time.0 = self.depth_time.0.saturating_add(weighted(bid_volume));
+            self.depth_time.1 = self.depth_time.1.saturating_add(weighted(ask_volume));
+            self.two_sided_time = self.two_sided_time.saturating_add(duration);
+        }
+        self.top_of_book = (time, bid, ask);
+    }
+
+    /// Create a report of all statistics as messages in the CSV formats
+    /// `SU, user_id, orders, cancels, rejects, trades, fill_ratio, cancel_to_trade_ratio`,
+    /// `SB, time_weighted_spread, average_bid_depth, average_ask_depth` and
+    /// `SM, message_type, count`. Values that are not available are written as `-`.
+    ///
+    /// # Return
+    /// - The messages of the report
+    pub fn report(&self) -> Vec<Message> {
+        let mut messages = Vec::new();
+        for (user, statistics) in self.users() {
+            messages.push(Message::UserStatistics {
+                user,
+                orders: statistics.orders,
+                cancels: statistics.cancels,
+                rejects: statistics.rejects,
+                trades: statistics.trades,
+                fill_ratio: statistics.fill_ratio(),
+                cancel_to_trade_ratio: statistics.cancel_to_trade_ratio(),
+            });
+        }
+        let book = match self.two_sided_time {
+            0 => Message::BookStatistics {
+                time_weighted_spread: None,
+                average_bid_depth: None,
+                average_ask_depth: None,
+            },
+            time => Message::BookStatistics {
+                time_weighted_spread: i64::try_from(self.spread_time / i128::from(time))
+                    .ok()
+                    .map(Price::from_units),
+                average_bid_depth: Some(self.depth_time.0 as f64 / time as f64),
+                average_ask_depth: Some(self.depth_time.1 as f64 / time as f64),
+            },
+        };
+        messages.push(book);
+        for (message_type, count) in &self.messages {
+            messages.push(Message::MessageStatistics {
+                message_type: message_type.to_string(),
+                count: *count,
+            });
+        }
+        messages
+    }
+}
+
+/// Divide two counts, `None` if the divisor is zero
+fn ratio(dividend: u64, divisor: u64) -> Option<f64> {
+    match divisor {
+        0 => None,
+        divisor => Some(dividend as f64 / divisor as f64),
+    }
+}
@@ -0,0 +1,37 @@
+//! Defines the status of an order as reported by the query API of an order book
+
+use std::fmt;
+
+/// Enumeration to specify where an order is in its lifecycle
+#[derive(Clone, Debug, PartialEq)]
+pub enum Status {
+    /// Order rests in the book with the given remaining quantity
+    Resting { qty: u64 },
+    /// Order was filled completely
+    Filled,
+    /// Order was cancelled, individually or by a mass cancellation
+    Cancelled,
+    /// Order was rejected, `None` as reason for orders that would cross the book
+    Rejected { reason: Option<String> },
+    /// Order expired at the end of the day or at its expiry timestamp
+    Expired,
+    /// Order was removed from the book by a flush
+    Flushed,
+}
+
+impl fmt::Display for Status {
+    /// Format the status as in the audit report, followed by the remaining quantity of resting
+    /// orders and the reason of rejected orders
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Status::Resting { qty } => write!(f, "RESTING, {}", qty),
+            Status::Filled => write!(f, "FILLED"),
+            Status::Cancelled => write!(f, "CANCELLED"),
+            Status::Rejected { reason } => {
+                write!(f, "REJECTED, {}", reason.as_deref().unwrap_or("-"))
+            }
+            Status::Expired => write!(f, "EXPIRED"),
+            Status::Flushed => write!(f, "FLUSHED"),
+        }
+    }
+}
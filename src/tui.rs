@@ -0,0 +1,332 @@
+//! Defines an interactive terminal UI that steps through an input file and shows the order book
+
+use crate::order_book::event::{Event, Message};
+use crate::order_book::format::Format;
+use crate::order_book::order::{self, Order, Side};
+use crate::order_book::price::Price;
+use crate::order_book::{jsonl, OrderBook};
+use ratatui::crossterm::event::{self, Event as TerminalEvent, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Style, Stylize};
+use ratatui::widgets::{Block, List, Paragraph, Row, Table};
+use ratatui::{DefaultTerminal, Frame};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::time::{Duration, Instant};
+
+/// Number of trades and events that are kept for display
+const HISTORY: usize = 500;
+
+/// Speeds the replay can run at in records per second
+const SPEEDS: [u32; 8] = [1, 2, 5, 10, 20, 50, 100, 1000];
+
+/// One record of the input file
+pub struct Record {
+    /// Line number of the record in the input file, starting at 1
+    pub line: u64,
+    /// Text of the record as shown in the UI
+    pub text: String,
+    pub order: Order,
+}
+
+/// Read all records of an input file in the CSV or JSON Lines format. Blank lines and comments are
+/// skipped.
+///
+/// # Args
+/// - `path`: Path of the input file
+/// - `format`: Format of the input file
+///
+/// # Return
+/// A `Result` containing the records or an `csv::Error`, if there is an issue with reading the
+/// provided file.
+///
+/// # Panics
+/// - If the input is in the binary format, which has no lines to step through
+pub fn load_records(path: &Path, format: Format) -> Result<Vec<Record>, csv::Error> {
+    if format == Format::Binary {
+        panic!("The terminal UI only supports the CSV and JSONL input formats");
+    }
+    let reader = BufReader::new(File::open(path)?);
+    let mut records = Vec::new();
+    // Every record is on its own line, so lines are parsed one by one to know their numbers
+    for (line, text) in (1..).zip(reader.lines()) {
+        let text = text?;
+        if text.trim().is_empty() || (format == Format::Csv && text.starts_with('#')) {
+            continue;
+        }
+        let order = match format {
+            Format::Jsonl => jsonl::parse_order(&text),
+            _ => {
+                let mut reader = order::csv_reader().from_reader(text.as_bytes());
+                match reader.records().next() {
+                    Some(record) => Order::from(&record?),
+                    None => continue,
+                }
+            }
+        };
+        records.push(Record { line, text, order });
+    }
+    Ok(records)
+}
+
+/// Replays records into an order book and keeps the output for display. Since an order book
+/// cannot undo a message, going back replays all records from a new order book.
+struct Replay<F> {
+    records: Vec<Record>,
+    /// Creates an order book sending its output to the given sender
+    new_order_book: F,
+    order_book: OrderBook,
+    output_receiver: Receiver<Event>,
+    /// Index of the next record to be processed
+    next: usize,
+    /// Last trades, newest first
+    trades: VecDeque<String>,
+    /// Last output messages of any kind, newest first
+    events: VecDeque<String>,
+}
+
+impl<F: Fn(Sender<Event>) -> OrderBook> Replay<F> {
+    fn new(records: Vec<Record>, new_order_book: F) -> Replay<F> {
+        let (output_sender, output_receiver) = channel();
+        Replay {
+            records,
+            order_book: new_order_book(output_sender),
+            new_order_book,
+            output_receiver,
+            next: 0,
+            trades: VecDeque::new(),
+            events: VecDeque::new(),
+        }
+    }
+
+    /// Start over with a new order book before the first record
+    fn restart(&mut self) {
+        let (output_sender, output_receiver) = channel();
+        self.order_book = (self.new_order_book)(output_sender);
+        self.output_receiver = output_receiver;
+        self.next = 0;
+        self.trades.clear();
+        self.events.clear();
+    }
+
+    /// Process the next record
+    ///
+    /// # Return
+    /// - `true` if a record was processed, `false` at the end of the input
+    fn step(&mut self) -> bool {
+        let order = match self.records.get(self.next) {
+            Some(record) => record.order.clone(),
+            None => return false,
+        };
+        self.order_book.add_order(order);
+        self.next += 1;
+        for event in self.output_receiver.try_iter() {
+            if let Message::Trade { .. } = event.message {
+                self.trades.push_front(event.message.to_string());
+                self.trades.truncate(HISTORY);
+            }
+            let message = match event.message {
+                Message::Flush => "-- flush --".to_string(),
+                message => message.to_string(),
+            };
+            self.events.push_front(message);
+            self.events.truncate(HISTORY);
+        }
+        true
+    }
+
+    /// Process all records up to and including the given line
+    ///
+    /// # Args
+    /// - `line`: Line number in the input file
+    fn jump(&mut self, line: u64) {
+        let processed = |replay: &Replay<F>| {
+            replay
+                .next
+                .checked_sub(1)
+                .map(|index| replay.records[index].line)
+        };
+        if processed(self).is_some_and(|processed| processed > line) {
+            self.restart();
+        }
+        while self.records.get(self.next).is_some_and(|r| r.line <= line) {
+            self.step();
+        }
+    }
+}
+
+/// State of the UI besides the replay
+struct Controls {
+    /// Records are processed automatically at the selected speed
+    running: bool,
+    /// Index into `SPEEDS`
+    speed: usize,
+    /// Digits of a line number that is being entered, `None` if no line number is requested
+    jump_to: Option<String>,
+}
+
+/// Step through the records of an input file in an interactive terminal UI, which shows the price
+/// levels of the book, the last trades and the last output messages
+///
+/// # Args
+/// - `name`: Name of the input shown in the UI
+/// - `records`: Records of the input file
+/// - `new_order_book`: Creates an order book sending its output to the given sender. Is called
+///   again whenever the replay starts over.
+///
+/// # Return
+/// A `Result` containing a `unit` or an `io::Error`, if there is an issue with the terminal
+pub fn run<F: Fn(Sender<Event>) -> OrderBook>(
+    name: &str,
+    records: Vec<Record>,
+    new_order_book: F,
+) -> Result<(), std::io::Error> {
+    let mut terminal = ratatui::init();
+    let result = run_in_terminal(&mut terminal, name, Replay::new(records, new_order_book));
+    ratatui::restore();
+    result
+}
+
+/// Run the event loop of the UI until the user quits
+fn run_in_terminal<F: Fn(Sender<Event>) -> OrderBook>(
+    terminal: &mut DefaultTerminal,
+    name: &str,
+    mut replay: Replay<F>,
+) -> Result<(), std::io::Error> {
+    let mut controls = Controls {
+        running: false,
+        speed: 0,
+        jump_to: None,
+    };
+    let mut last_step = Instant::now();
+    loop {
+        terminal.draw(|frame| draw(frame, name, &replay, &controls))?;
+        let interval = Duration::from_secs(1) / SPEEDS[controls.speed];
+        let timeout = match controls.running {
+            true => interval.saturating_sub(last_step.elapsed()),
+            false => Duration::from_millis(250),
+        };
+        if event::poll(timeout)? {
+            if let TerminalEvent::Key(key) = event::read()? {
+                if key.kind != KeyEventKind::Press {
+                    continue;
+                }
+                if let Some(digits) = &mut controls.jump_to {
+                    match key.code {
+                        KeyCode::Char(c) if c.is_ascii_digit() => digits.push(c),
+                        KeyCode::Backspace => {
+                            digits.pop();
+                        }
+                        KeyCode::Enter => {
+                            if let Ok(line) = digits.parse() {
+                                replay.jump(line);
+                            }
+                            controls.jump_to = None;
+                        }
+                        KeyCode::Esc => controls.jump_to = None,
+                        _ => (),
+                    }
+                    continue;
+                }
+                match key.code {
+                    KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                    KeyCode::Char(' ') => controls.running = !controls.running,
+                    KeyCode::Char('n') | KeyCode::Right | KeyCode::Enter => {
+                        controls.running = false;
+                        replay.step();
+                    }
+                    KeyCode::Char('+') => {
+                        controls.speed = (controls.speed + 1).min(SPEEDS.len() - 1)
+                    }
+                    KeyCode::Char('-') => controls.speed = controls.speed.saturating_sub(1),
+                    KeyCode::Char('g') => {
+                        controls.running = false;
+                        controls.jump_to = Some(String::new());
+                    }
+                    KeyCode::Char('r') => replay.restart(),
+                    _ => (),
+                }
+            }
+        }
+        if controls.running && last_step.elapsed() >= interval {
+            controls.running = replay.step();
+            last_step = Instant::now();
+        }
+    }
+}
+
+/// Draw the UI into a frame
+fn draw<F>(frame: &mut Frame, name: &str, replay: &Replay<F>, controls: &Controls) {
+    let [status, main, footer] = Layout::vertical([
+        Constraint::Length(1),
+        Constraint::Min(0),
+        Constraint::Length(2),
+    ])
+    .areas(frame.area());
+    let [ladder, trades, events] = Layout::horizontal([
+        Constraint::Percentage(40),
+        Constraint::Percentage(30),
+        Constraint::Percentage(30),
+    ])
+    .areas(main);
+
+    let mode = match controls.running {
+        true => format!("RUNNING at {} records/s", SPEEDS[controls.speed]),
+        false => format!("PAUSED, speed {} records/s", SPEEDS[controls.speed]),
+    };
+    let status_line = format!(
+        "{} | record {} of {} | {}",
+        name,
+        replay.next,
+        replay.records.len(),
+        mode
+    );
+    frame.render_widget(Paragraph::new(status_line).bold(), status);
+
+    draw_ladder(frame, ladder, &replay.order_book);
+    let list = |title: &'static str, lines: &VecDeque<String>, area: Rect| {
+        let items = lines.iter().take(area.height as usize).cloned();
+        List::new(items).block(Block::bordered().title(title))
+    };
+    frame.render_widget(list("Trades", &replay.trades, trades), trades);
+    frame.render_widget(list("Events", &replay.events, events), events);
+
+    let next = match replay.records.get(replay.next) {
+        Some(record) => format!("Next line {}: {}", record.line, record.text),
+        None => "End of input".to_string(),
+    };
+    let help = match &controls.jump_to {
+        Some(digits) => format!("Jump to line: {}_ (Enter to jump, Esc to cancel)", digits),
+        None => {
+            "n/Enter: step | Space: run/pause | +/-: speed | g: jump to line | r: restart | q: quit"
+                .to_string()
+        }
+    };
+    frame.render_widget(Paragraph::new(vec![next.into(), help.dim().into()]), footer);
+}
+
+/// Draw the price levels of both sides of the book, with the asks above the bids and the best
+/// prices next to each other
+fn draw_ladder(frame: &mut Frame, area: Rect, order_book: &OrderBook) {
+    // Leave room for the border, the header and the gap between the sides
+    let levels = (area.height.saturating_sub(4) / 2) as usize;
+    let row = |(price, qty, orders): (Price, u64, usize), color: Color| {
+        Row::new([price.to_string(), qty.to_string(), orders.to_string()])
+            .style(Style::default().fg(color))
+    };
+    let asks = order_book.depth(&Side::Sell, levels);
+    let bids = order_book.depth(&Side::Buy, levels);
+    let rows = asks
+        .into_iter()
+        .rev()
+        .map(|level| row(level, Color::Red))
+        .chain([Row::new(["", "", ""])])
+        .chain(bids.into_iter().map(|level| row(level, Color::Green)));
+    let table = Table::new(rows, [Constraint::Fill(1); 3])
+        .header(Row::new(["Price", "Quantity", "Orders"]).bold())
+        .block(Block::bordered().title("Book"));
+    frame.render_widget(table, area);
+}
@@ -0,0 +1,334 @@
+//! Defines a WebSocket server that streams the market data of an order book per symbol. Clients
+//! send JSON requests such as `{"op": "subscribe", "channel": "depth", "symbol": "IBM"}` for the
+//! channels `top_of_book`, `depth` and `trades`, and receive a snapshot followed by updates. Every
+//! message carries a sequence number per symbol and channel, which increases by one with every
+//! update, so clients can detect gaps.
+
+use crate::order_book::market_data::{side_index, Update};
+use crate::order_book::order::Side;
+use crate::order_book::price::Price;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::io::ErrorKind;
+use std::net::{TcpListener, TcpStream};
+use std::str::FromStr;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use tungstenite::Message;
+
+/// Number of trades per symbol that are sent in the snapshot of the trades channel
+const RECENT_TRADES: usize = 100;
+
+/// Time a client thread waits for requests before it sends pending updates
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Enumeration to specify the market data channels of a symbol
+#[derive(Clone, Copy, PartialEq)]
+pub enum Channel {
+    TopOfBook,
+    Depth,
+    Trades,
+}
+
+impl FromStr for Channel {
+    type Err = String;
+
+    /// Interpret the name of a channel
+    fn from_str(name: &str) -> Result<Channel, String> {
+        match name {
+            "top_of_book" => Ok(Channel::TopOfBook),
+            "depth" => Ok(Channel::Depth),
+            "trades" => Ok(Channel::Trades),
+            _ => Err(format!("Unknown channel {}", name)),
+        }
+    }
+}
+
+impl Channel {
+    /// Get the name of the channel in the messages
+    fn name(self) -> &'static str {
+        match self {
+            Channel::TopOfBook => "top_of_book",
+            Channel::Depth => "depth",
+            Channel::Trades => "trades",
+        }
+    }
+
+    /// Get the index of the channel in arrays that hold one entry per channel
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// Market data of one symbol as known from all updates so far
+#[derive(Default)]
+struct Book {
+    /// Sequence number of the last update per channel
+    sequences: [u64; 3],
+    /// Best price and total quantity of the buy and the sell side
+    top: [Option<(Price, u64)>; 2],
+    /// Total quantity and number of orders per price level of the buy and the sell side
+    levels: [BTreeMap<Price, (u64, u64)>; 2],
+    /// Last trades, oldest first
+    trades: VecDeque<Value>,
+}
+
+/// A client subscribed to one channel of a symbol
+struct Subscription {
+    client: u64,
+    symbol: String,
+    channel: Channel,
+    sender: Sender<String>,
+}
+
+/// Keeps the market data of all symbols and passes updates on to the subscribed clients
+#[derive(Default)]
+pub struct Hub {
+    books: HashMap<String, Book>,
+    subscriptions: Vec<Subscription>,
+}
+
+impl Hub {
+    /// Apply an update of the market data and send it to all subscribed clients
+    ///
+    /// # Args
+    /// - `update`: Update received from the order book
+    pub fn apply(&mut self, update: Update) {
+        let (symbol, channel, mut message) = match update {
+            Update::TopOfBook {
+                symbol,
+                side,
+                level,
+            } => {
+                let book = self.books.entry(symbol.clone()).or_default();
+                book.top[side_index(&side)] = level;
+                let mut message = json!({ "side": side_name(&side) });
+                message["price"] = level.map_or(Value::Null, |(price, _)| price_value(price));
+                message["qty"] = level.map_or(Value::Null, |(_, qty)| Value::from(qty));
+                (symbol, Channel::TopOfBook, message)
+            }
+            Update::Level {
+                symbol,
+                side,
+                price,
+                qty,
+                orders,
+            } => {
+                let levels = &mut self.books.entry(symbol.clone()).or_default().levels;
+                match qty {
+                    0 => levels[side_index(&side)].remove(&price),
+                    _ => levels[side_index(&side)].insert(price, (qty, orders)),
+                };
+                let message = json!({
+                    "side": side_name(&side),
+                    "price": price_value(price),
+                    "qty": qty,
+                    "orders": orders,
+                });
+                (symbol, Channel::Depth, message)
+            }
+            Update::Trade {
+                symbol,
+                trade_id,
+                timestamp,
+                price,
+                qty,
+                aggressor,
+            } => {
+                let trade = json!({
+                    "trade_id": trade_id,
+                    "timestamp": timestamp,
+                    "price": price_value(price),
+                    "qty": qty,
+                    "aggressor": aggressor.as_ref().map(side_name),
+                });
+                let trades = &mut self.books.entry(symbol.clone()).or_default().trades;
+                trades.push_back(trade.clone());
+                if trades.len() > RECENT_TRADES {
+                    trades.pop_front();
+                }
+                (symbol, Channel::Trades, trade)
+            }
+        };
+        let book = self.books.get_mut(&symbol).unwrap();
+        book.sequences[channel.index()] += 1;
+        message["type"] = Value::from("update");
+        message["channel"] = Value::from(channel.name());
+        message["symbol"] = Value::from(symbol.as_str());
+        message["sequence"] = Value::from(book.sequences[channel.index()]);
+        let message = message.to_string();
+        // Clients that disconnected are removed with their subscriptions
+        self.subscriptions.retain(|s| {
+            s.symbol != symbol || s.channel != channel || s.sender.send(message.clone()).is_ok()
+        });
+    }
+
+    /// Subscribe a client to a channel of a symbol. The client receives a snapshot of the channel
+    /// first and every update afterwards.
+    ///
+    /// # Args
+    /// - `client`: Identifier of the client
+    /// - `symbol`: Symbol to subscribe to
+    /// - `channel`: Channel to subscribe to
+    /// - `sender`: MPSC sender the messages for the client are sent to
+    pub fn subscribe(
+        &mut self,
+        client: u64,
+        symbol: &str,
+        channel: Channel,
+        sender: Sender<String>,
+    ) {
+        self.unsubscribe(client, symbol, channel);
+        let book = self.books.entry(symbol.to_string()).or_default();
+        let mut snapshot = match channel {
+            Channel::TopOfBook => {
+                let top = |side: &Side| match book.top[side_index(side)] {
+                    Some((price, qty)) => json!({ "price": price_value(price), "qty": qty }),
+                    None => Value::Null,
+                };
+                json!({ "bid": top(&Side::Buy), "ask": top(&Side::Sell) })
+            }
+            Channel::Depth => {
+                let levels = |side: &Side| {
+                    let levels = book.levels[side_index(side)].iter();
+                    let levels: Box<dyn Iterator<Item = _>> = match side {
+                        Side::Buy => Box::new(levels.rev()),
+                        Side::Sell => Box::new(levels),
+                    };
+                    levels
+                        .map(|(price, (qty, orders))| {
+                            json!({ "price": price_value(*price), "qty": qty, "orders": orders })
+                        })
+                        .collect::<Vec<_>>()
+                };
+                json!({ "bids": levels(&Side::Buy), "asks": levels(&Side::Sell) })
+            }
+            Channel::Trades => json!({ "trades": book.trades }),
+        };
+        snapshot["type"] = Value::from("snapshot");
+        snapshot["channel"] = Value::from(channel.name());
+        snapshot["symbol"] = Value::from(symbol);
+        snapshot["sequence"] = Value::from(book.sequences[channel.index()]);
+        if sender.send(snapshot.to_string()).is_ok() {
+            self.subscriptions.push(Subscription {
+                client,
+                symbol: symbol.to_string(),
+                channel,
+                sender,
+            });
+        }
+    }
+
+    /// Remove the subscription of a client to a channel of a symbol
+    ///
+    /// # Args
+    /// - `client`: Identifier of the client
+    /// - `symbol`: Symbol to unsubscribe from
+    /// - `channel`: Channel to unsubscribe from
+    pub fn unsubscribe(&mut self, client: u64, symbol: &str, channel: Channel) {
+        self.subscriptions
+            .retain(|s| s.client != client || s.symbol != symbol || s.channel != channel);
+    }
+
+    /// Remove all subscriptions of a client
+    ///
+    /// # Args
+    /// - `client`: Identifier of the client
+    pub fn disconnect(&mut self, client: u64) {
+        self.subscriptions.retain(|s| s.client != client);
+    }
+}
+
+/// Apply the market data updates of an order book to a hub and accept WebSocket clients, where
+/// every client is handled in its own thread. Clients are numbered in the order they connect,
+/// starting at 1.
+///
+/// # Args
+/// - `listener`: Listener to accept clients from
+/// - `updates`: Receiver of the market data updates of the order book
+pub fn serve(listener: TcpListener, updates: Receiver<Update>) {
+    let hub = Arc::new(Mutex::new(Hub::default()));
+    let publisher = hub.clone();
+    thread::spawn(move || {
+        for update in updates {
+            publisher.lock().unwrap().apply(update);
+        }
+    });
+    for (client, stream) in (1..).zip(listener.incoming()) {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(_) => continue,
+        };
+        let hub = hub.clone();
+        thread::spawn(move || handle_client(stream, client, &hub));
+    }
+}
+
+/// Answer the requests of a WebSocket client and send it the messages of its subscriptions until
+/// it disconnects
+///
+/// # Args
+/// - `stream`: Connection of the client
+/// - `client`: Identifier of the client
+/// - `hub`: Hub holding the market data
+pub fn handle_client(stream: TcpStream, client: u64, hub: &Mutex<Hub>) {
+    let mut socket = match tungstenite::accept(stream) {
+        Ok(socket) => socket,
+        Err(_) => return,
+    };
+    // Reads time out, so updates are sent while the client is silent
+    let timeout = socket.get_ref().set_read_timeout(Some(POLL_INTERVAL));
+    if timeout.is_err() {
+        return;
+    }
+    let (sender, receiver) = channel();
+    'session: loop {
+        for message in receiver.try_iter() {
+            if socket.send(Message::text(message)).is_err() {
+                break 'session;
+            }
+        }
+        let request = match socket.read() {
+            Ok(Message::Text(request)) => request,
+            Ok(Message::Close(_)) => break,
+            Ok(_) => continue,
+            Err(tungstenite::Error::Io(error))
+                if matches!(error.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) =>
+            {
+                continue
+            }
+            Err(_) => break,
+        };
+        let request: Value = serde_json::from_str(&request).unwrap_or_default();
+        let channel = request["channel"].as_str().map(str::parse::<Channel>);
+        let mut hub = hub.lock().unwrap();
+        match (request["op"].as_str(), request["symbol"].as_str(), channel) {
+            (Some("subscribe"), Some(symbol), Some(Ok(channel))) => {
+                hub.subscribe(client, symbol, channel, sender.clone())
+            }
+            (Some("unsubscribe"), Some(symbol), Some(Ok(channel))) => {
+                hub.unsubscribe(client, symbol, channel)
+            }
+            _ => {
+                let error = json!({ "type": "error", "error": "MALFORMED_REQUEST" });
+                let _ = sender.send(error.to_string());
+            }
+        }
+    }
+    hub.lock().unwrap().disconnect(client);
+}
+
+/// Get the name of a side in the messages
+fn side_name(side: &Side) -> &'static str {
+    match side {
+        Side::Buy => "buy",
+        Side::Sell => "sell",
+    }
+}
+
+/// Convert a price into a JSON string, which keeps all its decimals
+fn price_value(price: Price) -> Value {
+    Value::from(price.to_string())
+}